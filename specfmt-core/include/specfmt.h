@@ -0,0 +1,52 @@
+/* The C interface to specfmt-core. Build the library with:
+ *
+ *   cargo rustc -p specfmt-core --release --features cdylib --crate-type cdylib
+ *
+ * Options and buffers returned by these functions are owned by the caller, who
+ * must free them with specfmt_options_free() and specfmt_buffer_free(). */
+
+#ifndef SPECFMT_H
+#define SPECFMT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SPECFMT_OK 0
+#define SPECFMT_INVALID_ARGUMENT 1
+#define SPECFMT_INVALID_UTF8 2
+
+typedef struct SpecfmtOptions SpecfmtOptions;
+
+/* Bytes allocated by the formatter. Not NUL-terminated. */
+typedef struct SpecfmtBuffer {
+  uint8_t *data;
+  size_t length;
+} SpecfmtBuffer;
+
+/* The default options follow the WHATWG conventions. */
+SpecfmtOptions *specfmt_options_new(void);
+void specfmt_options_free(SpecfmtOptions *options);
+/* A `wrap` of 0 never splits lines. Returns SPECFMT_INVALID_ARGUMENT if `wrap`
+ * is narrower than 20 columns. */
+int32_t specfmt_options_set_wrap(SpecfmtOptions *options, size_t wrap);
+void specfmt_options_set_wrap_attributes(SpecfmtOptions *options, bool wrap_attributes);
+/* A negative width aligns continuation lines with the text after a list
+ * marker. */
+void specfmt_options_set_continuation_indent(SpecfmtOptions *options, int32_t width);
+
+/* Formats the `length` bytes of UTF-8 at `source` with `options` (or the
+ * defaults, if NULL) into `output`. */
+int32_t specfmt_format_buffer(const uint8_t *source, size_t length,
+                              const SpecfmtOptions *options, SpecfmtBuffer *output);
+void specfmt_buffer_free(SpecfmtBuffer buffer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SPECFMT_H */
@@ -0,0 +1,66 @@
+// Line breaking inside runs of Chinese and Japanese text, which doesn't put
+// spaces between words. Lines may break between any two CJK characters, except
+// where the basic kinsoku shori rules forbid it: before closing punctuation and
+// small kana, which can't start a line, and after opening brackets, which can't
+// end one.
+//
+// Breaks are only ever made between two CJK characters, and lines that end and
+// start with CJK characters are joined without a space, so breaking doesn't
+// change the text (other than how browsers render the line break) and
+// rewrapping it again is stable. Korean puts spaces between words, so Hangul
+// isn't CJK for this purpose.
+
+use unicode_width::UnicodeWidthChar;
+
+// The characters that can't start a line.
+const NO_START: &str = "、。，．・：；？！ー－〜）」』】〕〉》〗〙〛｝］｠\
+    ぁぃぅぇぉっゃゅょゎゕゖァィゥェォッャュョヮヵヶㇰㇱㇲㇳㇴㇵㇶㇷㇸㇹㇺㇻㇼㇽㇾㇿ々〻ゝゞヽヾ";
+// The characters that can't end a line.
+const NO_END: &str = "（「『【〔〈《〖〘〚｛［｟";
+
+// Whether `c` is a Chinese or Japanese character, including CJK punctuation
+// and fullwidth forms.
+pub fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3000}'..='\u{303F}' // CJK Symbols and Punctuation
+        | '\u{3040}'..='\u{30FF}' // Hiragana and Katakana
+        | '\u{31F0}'..='\u{31FF}' // Katakana Phonetic Extensions
+        | '\u{3400}'..='\u{4DBF}' // CJK Unified Ideographs Extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+        | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+        | '\u{FF00}'..='\u{FFEF}' // Halfwidth and Fullwidth Forms
+        | '\u{20000}'..='\u{2FFFF}' // Supplementary Ideographic Plane
+    )
+}
+
+// Whether a line may break between `before` and `after`.
+fn can_break_between(before: char, after: char) -> bool {
+    is_cjk(before) && is_cjk(after) && !NO_END.contains(before) && !NO_START.contains(after)
+}
+
+// Whether `word` has anywhere to break it.
+pub fn has_break(word: &str) -> bool {
+    word.chars()
+        .zip(word.chars().skip(1))
+        .any(|(before, after)| can_break_between(before, after))
+}
+
+// Returns the byte index of the last place that `word` can break such that the
+// part before it is at most `max_width` columns wide, if any.
+pub fn last_break(word: &str, max_width: usize) -> Option<usize> {
+    let mut width = 0;
+    let mut last_break = None;
+    let mut chars = word.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        width += c.width().unwrap_or(0);
+        if width > max_width {
+            break;
+        }
+        if let Some(&(i, next)) = chars.peek() {
+            if can_break_between(c, next) {
+                last_break = Some(i);
+            }
+        }
+    }
+    last_break
+}
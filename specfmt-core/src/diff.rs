@@ -0,0 +1,313 @@
+//! Line-based diffing between a spec and its formatted counterpart, used to
+//! report what `specfmt` would change without writing anything. This is a plain
+//! implementation of Myers' O(ND) algorithm ("An O(ND) Difference Algorithm and
+//! Its Variations", 1986). Formatting changes are usually small and localized,
+//! so we trim the common prefix and suffix of both sides before diffing.
+
+// The number of unchanged lines printed around each hunk of a unified diff.
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A contiguous run of changed lines, along with up to `CONTEXT_LINES` of
+/// unchanged context on either side. Line numbers are 1-based, as they are in
+/// unified diffs and editors.
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    /// Each line is prefixed with " ", "-", or "+", except for the "\ No
+    /// newline at end of file" marker.
+    pub lines: Vec<String>,
+}
+
+impl Hunk {
+    /// Returns the 1-based, inclusive range of lines in `old` that this hunk
+    /// changes, excluding context. A hunk that only inserts lines is attributed
+    /// to the line it inserts after (or the first line, for insertions at the
+    /// start of the file).
+    pub fn changed_old_lines(&self) -> (usize, usize) {
+        let mut line = self.old_start;
+        let mut changed: Option<(usize, usize)> = None;
+        for hunk_line in &self.lines {
+            if hunk_line.starts_with('\\') {
+                continue;
+            }
+            if hunk_line.starts_with('+') {
+                let insertion = usize::max(line - 1, 1);
+                changed.get_or_insert((insertion, insertion));
+                continue;
+            }
+            if hunk_line.starts_with('-') {
+                changed = match changed {
+                    Some((start, _)) if start < line => Some((start, line)),
+                    _ => Some((line, line)),
+                };
+            }
+            line += 1;
+        }
+        changed.unwrap_or((self.old_start, self.old_start))
+    }
+
+    /// Returns the `@@ -1,2 +1,3 @@` line that introduces this hunk in a unified
+    /// diff.
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len)
+        )
+    }
+}
+
+// Unified diffs name an empty range by the line *before* it.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start - 1),
+        1 => format!("{}", start),
+        _ => format!("{},{}", start, len),
+    }
+}
+
+// Computes the edit script that turns `old` into `new`.
+fn edit_script(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut edits = vec![Edit::Equal; prefix];
+    edits.append(&mut myers(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    ));
+    edits.append(&mut vec![Edit::Equal; suffix]);
+    edits
+}
+
+fn myers(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = n + m;
+    let index = |k: isize| (k + max) as usize;
+
+    // `v[index(k)]` is the furthest x reached on diagonal k. `trace[d]` is a
+    // snapshot of diagonals -d..=d after the d-th step, which is all we need to
+    // walk back through the edit graph.
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'outer: for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                trace.push(v[index(-d)..=index(d)].to_vec());
+                break 'outer;
+            }
+        }
+        trace.push(v[index(-d)..=index(d)].to_vec());
+    }
+
+    let mut edits = Vec::<Edit>::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[d as usize - 1];
+        let get = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = get(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        edits.push(if x == previous_x {
+            Edit::Insert
+        } else {
+            Edit::Delete
+        });
+        x = previous_x;
+        y = previous_y;
+    }
+    while x > 0 && y > 0 {
+        edits.push(Edit::Equal);
+        x -= 1;
+        y -= 1;
+    }
+
+    edits.reverse();
+    edits
+}
+
+/// Groups the differences between `old` and `new` into hunks.
+pub fn hunks(old: &str, new: &str) -> Vec<Hunk> {
+    // Lines keep their terminating newline, so that a missing newline at the
+    // end of the file is a difference like any other.
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = edit_script(&old, &new);
+
+    // Walk the edit script, tracking the 0-based position of each edit in
+    // `old` and `new`.
+    let mut positions = Vec::<(Edit, usize, usize)>::new();
+    let (mut i, mut j) = (0, 0);
+    for edit in edits {
+        positions.push((edit, i, j));
+        match edit {
+            Edit::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+
+    let mut return_hunks = Vec::<Hunk>::new();
+    let mut start = 0;
+    while start < positions.len() {
+        if positions[start].0 == Edit::Equal {
+            start += 1;
+            continue;
+        }
+
+        // Extend the hunk for as long as the changes are separated by no more
+        // than twice the context, so that neighbouring hunks don't overlap.
+        let mut end = start;
+        let mut last_change = start;
+        while end < positions.len() {
+            if positions[end].0 != Edit::Equal {
+                last_change = end;
+            } else if end - last_change > 2 * CONTEXT_LINES {
+                break;
+            }
+            end += 1;
+        }
+        let first = start.saturating_sub(CONTEXT_LINES);
+        let last = usize::min(last_change + CONTEXT_LINES + 1, positions.len());
+
+        let mut hunk = Hunk {
+            old_start: positions[first].1 + 1,
+            old_len: 0,
+            new_start: positions[first].2 + 1,
+            new_len: 0,
+            lines: Vec::new(),
+        };
+        for &(edit, i, j) in &positions[first..last] {
+            match edit {
+                Edit::Equal => {
+                    hunk.old_len += 1;
+                    hunk.new_len += 1;
+                    push_line(&mut hunk.lines, ' ', old[i]);
+                }
+                Edit::Delete => {
+                    hunk.old_len += 1;
+                    push_line(&mut hunk.lines, '-', old[i]);
+                }
+                Edit::Insert => {
+                    hunk.new_len += 1;
+                    push_line(&mut hunk.lines, '+', new[j]);
+                }
+            }
+        }
+        return_hunks.push(hunk);
+        start = last;
+    }
+
+    return_hunks
+}
+
+fn push_line(lines: &mut Vec<String>, prefix: char, line: &str) {
+    match line.strip_suffix('\n') {
+        Some(line) => lines.push(format!("{}{}", prefix, line)),
+        None => {
+            lines.push(format!("{}{}", prefix, line));
+            lines.push(String::from("\\ No newline at end of file"));
+        }
+    }
+}
+
+/// Renders the differences between `old` and `new` as a unified diff of `path`.
+/// Returns an empty string if there are no differences.
+pub fn unified_diff(old: &str, new: &str, path: &str) -> String {
+    let hunks = hunks(old, new);
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = format!("--- a/{}\n+++ b/{}\n", path, path);
+    for hunk in hunks {
+        diff.push_str(&hunk.header());
+        diff.push('\n');
+        for line in hunk.lines {
+            diff.push_str(&line);
+            diff.push('\n');
+        }
+    }
+    diff
+}
+
+/// Returns `old` with only the hunks of `hunks` for which `selected` is true
+/// applied. `hunks` must describe the changes from `old` to some other text, as
+/// returned by `hunks()`.
+pub fn apply_hunks(old: &str, hunks: &[Hunk], selected: &[bool]) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let mut result = String::new();
+    let mut next_line = 0;
+    for (hunk, &selected) in hunks.iter().zip(selected) {
+        let hunk_start = hunk.old_start - 1;
+        let hunk_end = hunk_start + hunk.old_len;
+        for line in &old_lines[next_line..hunk_start] {
+            result.push_str(line);
+        }
+        next_line = hunk_end;
+        if !selected {
+            for line in &old_lines[hunk_start..hunk_end] {
+                result.push_str(line);
+            }
+            continue;
+        }
+
+        for (i, line) in hunk.lines.iter().enumerate() {
+            if line.starts_with('-') || line.starts_with('\\') {
+                continue;
+            }
+            result.push_str(&line[1..]);
+            let missing_newline = hunk
+                .lines
+                .get(i + 1)
+                .is_some_and(|next| next.starts_with('\\'));
+            if !missing_newline {
+                result.push('\n');
+            }
+        }
+    }
+    for line in &old_lines[next_line..] {
+        result.push_str(line);
+    }
+
+    result
+}
@@ -0,0 +1,174 @@
+//! A C-compatible interface to the formatter, for spec pipelines that aren't
+//! written in Rust (Wattsi tooling, Python build scripts) and would rather embed
+//! it than spawn `specfmt` for every file. Only available with the `cdylib`
+//! feature. Build the shared library with:
+//!
+//! ```sh
+//! cargo rustc -p specfmt-core --release --features cdylib --crate-type cdylib
+//! ```
+//!
+//! and see `include/specfmt.h` for the declarations. Options and buffers
+//! returned by these functions are owned by the caller, who must free them with
+//! `specfmt_options_free()` and `specfmt_buffer_free()`. Called from Rust, a
+//! round trip looks like this:
+//!
+//! ```
+//! use specfmt_core::ffi::*;
+//!
+//! let source = "<p>A paragraph that\nwas wrapped early.";
+//! let mut output = SpecfmtBuffer {
+//!     data: std::ptr::null_mut(),
+//!     length: 0,
+//! };
+//! unsafe {
+//!     let invalid = [0xff];
+//!     let status = specfmt_format_buffer(invalid.as_ptr(), 1, std::ptr::null(), &mut output);
+//!     assert_eq!(status, SPECFMT_INVALID_UTF8);
+//!
+//!     let options = specfmt_options_new();
+//!     assert_eq!(specfmt_options_set_wrap(options, 10), SPECFMT_INVALID_ARGUMENT);
+//!     assert_eq!(specfmt_options_set_wrap(options, 30), SPECFMT_OK);
+//!     let status = specfmt_format_buffer(source.as_ptr(), source.len(), options, &mut output);
+//!     assert_eq!(status, SPECFMT_OK);
+//!     let formatted = std::slice::from_raw_parts(output.data, output.length);
+//!     assert_eq!(formatted, b"<p>A paragraph that was\nwrapped early.");
+//!     specfmt_buffer_free(output);
+//!     specfmt_options_free(options);
+//! }
+//! ```
+
+use super::rewrapper::ContinuationIndent;
+use super::rewrapper::RewrapOptions;
+use super::rewrapper::MIN_COLUMN_LENGTH;
+
+/// Bytes allocated by the formatter, which must be freed with
+/// `specfmt_buffer_free()`. The bytes are not NUL-terminated.
+#[repr(C)]
+pub struct SpecfmtBuffer {
+    pub data: *mut u8,
+    pub length: usize,
+}
+
+/// The return values of the functions that can fail.
+pub const SPECFMT_OK: i32 = 0;
+pub const SPECFMT_INVALID_ARGUMENT: i32 = 1;
+pub const SPECFMT_INVALID_UTF8: i32 = 2;
+
+/// Returns the default options, which follow the WHATWG conventions.
+#[no_mangle]
+pub extern "C" fn specfmt_options_new() -> *mut RewrapOptions {
+    Box::into_raw(Box::default())
+}
+
+/// # Safety
+///
+/// `options` must be null, or have been returned by `specfmt_options_new()`
+/// and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn specfmt_options_free(options: *mut RewrapOptions) {
+    if !options.is_null() {
+        drop(Box::from_raw(options));
+    }
+}
+
+/// Wraps lines to `wrap` columns, or never splits them if `wrap` is 0. Returns
+/// `SPECFMT_INVALID_ARGUMENT`, leaving `options` alone, if `wrap` is narrower
+/// than `MIN_COLUMN_LENGTH`.
+///
+/// # Safety
+///
+/// `options` must have been returned by `specfmt_options_new()` and not freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn specfmt_options_set_wrap(options: *mut RewrapOptions, wrap: usize) -> i32 {
+    let Some(options) = options.as_mut() else {
+        return SPECFMT_INVALID_ARGUMENT;
+    };
+    if wrap != 0 && wrap < MIN_COLUMN_LENGTH {
+        return SPECFMT_INVALID_ARGUMENT;
+    }
+    options.column_length = wrap;
+    SPECFMT_OK
+}
+
+/// # Safety
+///
+/// `options` must have been returned by `specfmt_options_new()` and not freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn specfmt_options_set_wrap_attributes(
+    options: *mut RewrapOptions,
+    wrap_attributes: bool,
+) {
+    if let Some(options) = options.as_mut() {
+        options.wrap_attributes = wrap_attributes;
+    }
+}
+
+/// Indents continuation lines by `width` extra spaces, or aligns them with the
+/// text after a list marker if `width` is negative.
+///
+/// # Safety
+///
+/// `options` must have been returned by `specfmt_options_new()` and not freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn specfmt_options_set_continuation_indent(
+    options: *mut RewrapOptions,
+    width: i32,
+) {
+    if let Some(options) = options.as_mut() {
+        options.continuation_indent = match usize::try_from(width) {
+            Ok(width) => ContinuationIndent::Fixed(width),
+            Err(_) => ContinuationIndent::Marker,
+        };
+    }
+}
+
+/// Formats all of the `length` bytes of UTF-8 at `source` according to
+/// `options` (or the default options, if null), and stores the formatted spec
+/// in `output`. Returns `SPECFMT_OK` on success, in which case the caller owns
+/// `output`.
+///
+/// # Safety
+///
+/// `source` must point to `length` readable bytes, `options` must be null or a
+/// live value returned by `specfmt_options_new()`, and `output` must point to
+/// a writable `SpecfmtBuffer`.
+#[no_mangle]
+pub unsafe extern "C" fn specfmt_format_buffer(
+    source: *const u8,
+    length: usize,
+    options: *const RewrapOptions,
+    output: *mut SpecfmtBuffer,
+) -> i32 {
+    if source.is_null() || output.is_null() {
+        return SPECFMT_INVALID_ARGUMENT;
+    }
+    let source = match std::str::from_utf8(std::slice::from_raw_parts(source, length)) {
+        Ok(source) => source,
+        Err(_) => return SPECFMT_INVALID_UTF8,
+    };
+    let default_options = RewrapOptions::default();
+    let options = options.as_ref().unwrap_or(&default_options);
+
+    let formatted = super::format(source, options).lines.join("\n");
+    let formatted = formatted.into_bytes().into_boxed_slice();
+    let length = formatted.len();
+    *output = SpecfmtBuffer {
+        data: Box::into_raw(formatted) as *mut u8,
+        length,
+    };
+    SPECFMT_OK
+}
+
+/// # Safety
+///
+/// `buffer` must have been filled in by the formatter, and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn specfmt_buffer_free(buffer: SpecfmtBuffer) {
+    if !buffer.data.is_null() {
+        let bytes = std::ptr::slice_from_raw_parts_mut(buffer.data, buffer.length);
+        drop(Box::from_raw(bytes));
+    }
+}
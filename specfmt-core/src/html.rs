@@ -0,0 +1,209 @@
+// A lightweight HTML tokenizer, just precise enough to find the tags and
+// comments that delimit the blocks exempt from formatting. Unlike searching
+// lines for strings like "<pre", it knows that a `>` or `<pre>` inside an
+// attribute value (like `<p data-sample="<pre>">`) doesn't start or end a tag,
+// that tags in comments and in raw text elements (like `<script>`) aren't
+// tags, and that a tag can be split across lines between its attributes.
+//
+// It is not a conforming HTML tokenizer: it only reports tag names, and
+// anything it doesn't understand is treated as text. It does understand the
+// CDATA sections and XML processing instructions that some W3C sources still
+// carry, which end at `]]>` and `?>` rather than at the first `>`.
+
+// The elements whose content is raw text, which ends only at their end tag.
+const RAW_TEXT_ELEMENTS: [&str; 6] = ["script", "style", "xmp", "textarea", "title", "plaintext"];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Token {
+    // A start tag, reported as soon as its (lowercased) name is complete, even
+    // if its attributes continue on later lines.
+    StartTag(String),
+    // An end tag, with its lowercased name.
+    EndTag(String),
+    // The `<!--` that starts a comment.
+    CommentStart,
+    // The `-->` that ends a comment.
+    CommentEnd,
+    // The `<![CDATA[` that starts a CDATA section.
+    CdataStart,
+    // The `]]>` that ends a CDATA section.
+    CdataEnd,
+    // The `<?` that starts a processing instruction, like `<?xml ... ?>`.
+    ProcessingInstructionStart,
+    // The `?>` that ends a processing instruction.
+    ProcessingInstructionEnd,
+}
+
+enum State {
+    Data,
+    // In the name of a start or end tag.
+    TagName {
+        end: bool,
+        name: String,
+    },
+    // In a tag after its name, possibly in a quoted attribute value. `raw_text`
+    // is the name of the raw text element that the tag starts, if any.
+    Attributes {
+        quote: Option<char>,
+        raw_text: Option<String>,
+    },
+    Comment,
+    Cdata,
+    ProcessingInstruction,
+    // In a declaration like `<!DOCTYPE html>`, which ends at the next `>`.
+    Declaration,
+    RawText(String),
+}
+
+pub struct Tokenizer {
+    state: State,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Tokenizer { state: State::Data }
+    }
+}
+
+impl Tokenizer {
+    // Whether the tokenizer is in text, rather than in a tag, comment, or raw
+    // text element.
+    pub fn in_text(&self) -> bool {
+        matches!(self.state, State::Data)
+    }
+
+    // Returns the tokens on `line`, which continues from where the line
+    // previously passed to this left off.
+    pub fn tokens(&mut self, line: &str) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            let c = rest.chars().next().unwrap();
+            i += c.len_utf8();
+            match &mut self.state {
+                State::Data => {
+                    if c != '<' {
+                        continue;
+                    }
+                    let after = &line[i..];
+                    if after.starts_with("!--") {
+                        tokens.push(Token::CommentStart);
+                        self.state = State::Comment;
+                        i += 3;
+                    } else if after.starts_with("![CDATA[") {
+                        tokens.push(Token::CdataStart);
+                        self.state = State::Cdata;
+                        i += 8;
+                    } else if after.starts_with('?') {
+                        tokens.push(Token::ProcessingInstructionStart);
+                        self.state = State::ProcessingInstruction;
+                        i += 1;
+                    } else if after.starts_with('!') {
+                        self.state = State::Declaration;
+                    } else if after.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                        self.state = State::TagName {
+                            end: false,
+                            name: String::new(),
+                        };
+                    } else if after.starts_with("/")
+                        && after[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+                    {
+                        self.state = State::TagName {
+                            end: true,
+                            name: String::new(),
+                        };
+                        i += 1;
+                    }
+                }
+                State::TagName { end, name } => {
+                    if c.is_ascii_alphanumeric() || c == '-' || c == ':' || c == '_' {
+                        name.push(c.to_ascii_lowercase());
+                        continue;
+                    }
+                    let name = std::mem::take(name);
+                    let raw_text =
+                        (!*end && RAW_TEXT_ELEMENTS.contains(&name.as_str())).then(|| name.clone());
+                    tokens.push(if *end {
+                        Token::EndTag(name)
+                    } else {
+                        Token::StartTag(name)
+                    });
+                    self.state = State::Attributes {
+                        quote: None,
+                        raw_text,
+                    };
+                    // Let the attributes state handle the character that ended
+                    // the name, like `>`.
+                    i -= c.len_utf8();
+                }
+                State::Attributes { quote, raw_text } => match (*quote, c) {
+                    (Some(q), c) if c == q => *quote = None,
+                    (Some(_), _) => {}
+                    (None, '"' | '\'') => *quote = Some(c),
+                    (None, '>') => {
+                        self.state = match raw_text.take() {
+                            Some(name) => State::RawText(name),
+                            None => State::Data,
+                        };
+                    }
+                    (None, _) => {}
+                },
+                State::Comment => {
+                    if c == '-' && rest.starts_with("-->") {
+                        tokens.push(Token::CommentEnd);
+                        self.state = State::Data;
+                        i += 2;
+                    }
+                }
+                State::Cdata => {
+                    if c == ']' && rest.starts_with("]]>") {
+                        tokens.push(Token::CdataEnd);
+                        self.state = State::Data;
+                        i += 2;
+                    }
+                }
+                State::ProcessingInstruction => {
+                    if c == '?' && rest.starts_with("?>") {
+                        tokens.push(Token::ProcessingInstructionEnd);
+                        self.state = State::Data;
+                        i += 1;
+                    }
+                }
+                State::Declaration => {
+                    if c == '>' {
+                        self.state = State::Data;
+                    }
+                }
+                State::RawText(name) => {
+                    // `<plaintext>` never ends.
+                    if c != '<' || name == "plaintext" {
+                        continue;
+                    }
+                    let after = &line[i..];
+                    let closes = after.strip_prefix('/').is_some_and(|after| {
+                        after.len() >= name.len()
+                            && after.is_char_boundary(name.len())
+                            && after[..name.len()].eq_ignore_ascii_case(name)
+                            && !after[name.len()..]
+                                .starts_with(|c: char| c.is_ascii_alphanumeric() || c == '-')
+                    });
+                    if closes {
+                        self.state = State::TagName {
+                            end: true,
+                            name: String::new(),
+                        };
+                        i += 1;
+                    }
+                }
+            }
+        }
+
+        // A tag name ends at the end of a line, like `<pre` followed by its
+        // attributes on the next line.
+        if let State::TagName { .. } = self.state {
+            tokens.extend(self.tokens(" "));
+        }
+        tokens
+    }
+}
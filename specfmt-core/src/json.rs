@@ -0,0 +1,243 @@
+//! An opt-in pass that pretty-prints the JSON examples of a spec with
+//! consistent 2-space indentation. Example payloads are frequently pasted into
+//! specs minified, or indented however the author's editor felt like at the
+//! time. We only ever touch blocks whose contents are valid JSON; anything else
+//! (comments, `...` elisions, etc.) is left exactly as the author wrote it.
+//!
+//! Unlike the rest of the formatter, this pass operates on the whole spec as a
+//! string before it is split into `Line`s, since it changes the number of lines
+//! in the spec.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // Matches `<pre class="highlight json">`, `<pre class=lang-json>`, and
+    // `<pre highlight=json>`, as long as the open tag is alone on its line.
+    static ref JSON_PRE_OPEN: Regex =
+        Regex::new(r#"^\s*<pre\b[^>]*\b(?:class|highlight)="?[^">]*\bjson\b[^>]*>$"#).unwrap();
+    static ref JSON_FENCE_OPEN: Regex = Regex::new(r#"^\s*(```+|~~~+)\s*json\s*$"#).unwrap();
+    static ref JSON_SCALAR: Regex =
+        Regex::new(r#"^(?:true|false|null|-?(?:0|[1-9][0-9]*)(?:\.[0-9]+)?(?:[eE][+-]?[0-9]+)?)$"#)
+            .unwrap();
+}
+
+const JSON_INDENT: &str = "  ";
+
+/// Returns `source` with the JSON in its `<pre class="highlight json">` blocks
+/// and ```` ```json ```` fences pretty-printed.
+pub fn format_json_blocks(source: &str) -> String {
+    let in_scope = vec![true; source.split('\n').count()];
+    format_json_blocks_in_scope(source, &in_scope).0
+}
+
+/// Like [`format_json_blocks()`], but only pretty-prints the blocks that have a
+/// line (counting the lines that open and close them) for which `in_scope`,
+/// indexed by the 0-based line number in `source`, is true. Lines past the end
+/// of `in_scope` are out of scope.
+///
+/// Also returns whether each line of the result is in scope: the lines of the
+/// blocks that were pretty-printed are, and every other line is if its line in
+/// `source` was.
+pub fn format_json_blocks_in_scope(source: &str, in_scope: &[bool]) -> (String, Vec<bool>) {
+    let lines: Vec<&str> = source.split('\n').collect();
+    let is_in_scope = |i: usize| in_scope.get(i).copied().unwrap_or(false);
+    let mut return_lines = Vec::<String>::new();
+    let mut return_in_scope = Vec::<bool>::new();
+
+    let mut i = 0;
+    while i < lines.len() {
+        return_lines.push(lines[i].to_string());
+        return_in_scope.push(is_in_scope(i));
+        let close = if JSON_PRE_OPEN.is_match(lines[i]) {
+            find_block_end(&lines, i + 1, |line| {
+                line.trim_start().starts_with("</pre>")
+            })
+        } else if let Some(fence) = JSON_FENCE_OPEN.captures(lines[i]) {
+            let fence = fence[1].to_string();
+            find_block_end(&lines, i + 1, |line| line.trim() == fence)
+        } else {
+            None
+        };
+
+        let close = match close {
+            Some(close) if (i..=close).any(is_in_scope) => close,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let contents = &lines[i + 1..close];
+        match pretty_print(&contents.join("\n")) {
+            Some(pretty) => {
+                let indent = block_indent(contents);
+                for pretty_line in pretty {
+                    return_lines.push(indent.to_string() + &pretty_line);
+                    return_in_scope.push(true);
+                }
+            }
+            None => {
+                return_lines.extend(contents.iter().map(|line| line.to_string()));
+                return_in_scope.extend((i + 1..close).map(is_in_scope));
+            }
+        }
+        i = close;
+    }
+
+    (return_lines.join("\n"), return_in_scope)
+}
+
+fn find_block_end(lines: &[&str], start: usize, is_close: impl Fn(&str) -> bool) -> Option<usize> {
+    (start..lines.len()).find(|&i| is_close(lines[i]))
+}
+
+// The JSON inside of a block keeps the smallest indentation that the author
+// used for any of its (non-empty) lines.
+fn block_indent<'a>(lines: &[&'a str]) -> &'a str {
+    lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .min_by_key(|indent| indent.len())
+        .unwrap_or("")
+}
+
+#[derive(PartialEq)]
+enum Token<'a> {
+    Punctuation(char),
+    // Strings, numbers, `true`, `false`, and `null`. These are reproduced
+    // verbatim, so we never change how a number or escape sequence is spelled.
+    Value(&'a str),
+}
+
+fn tokenize(json: &str) -> Option<Vec<Token<'_>>> {
+    let mut tokens = Vec::<Token>::new();
+    let mut chars = json.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '{' | '}' | '[' | ']' | ':' | ',' => tokens.push(Token::Punctuation(c)),
+            '"' => {
+                let mut end = None;
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next()?;
+                        }
+                        '"' => {
+                            end = Some(i + 1);
+                            break;
+                        }
+                        '\n' => return None,
+                        _ => {}
+                    }
+                }
+                tokens.push(Token::Value(&json[start..end?]));
+            }
+            _ => {
+                let mut end = json.len();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || "{}[]:,\"".contains(c) {
+                        end = i;
+                        break;
+                    }
+                    chars.next();
+                }
+                let scalar = &json[start..end];
+                if !JSON_SCALAR.is_match(scalar) {
+                    return None;
+                }
+                tokens.push(Token::Value(scalar));
+            }
+        }
+    }
+
+    Some(tokens)
+}
+
+// Returns the pretty-printed lines of `json`, or `None` if it is not a single
+// valid JSON value.
+fn pretty_print(json: &str) -> Option<Vec<String>> {
+    let tokens = tokenize(json)?;
+    let mut printer = Printer {
+        tokens: &tokens,
+        position: 0,
+        lines: vec![String::new()],
+    };
+    printer.value(0)?;
+    if printer.position != tokens.len() {
+        return None;
+    }
+    Some(printer.lines)
+}
+
+struct Printer<'a> {
+    tokens: &'a [Token<'a>],
+    position: usize,
+    lines: Vec<String>,
+}
+
+impl<'a> Printer<'a> {
+    fn next(&mut self) -> Option<&'a Token<'a>> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        Some(token)
+    }
+
+    fn peek_is(&self, c: char) -> bool {
+        self.tokens.get(self.position) == Some(&Token::Punctuation(c))
+    }
+
+    fn push(&mut self, text: &str) {
+        self.lines.last_mut().unwrap().push_str(text);
+    }
+
+    fn new_line(&mut self, depth: usize) {
+        self.lines.push(JSON_INDENT.repeat(depth));
+    }
+
+    fn value(&mut self, depth: usize) -> Option<()> {
+        match self.next()? {
+            Token::Value(value) => self.push(value),
+            Token::Punctuation('{') => self.container('}', depth, true)?,
+            Token::Punctuation('[') => self.container(']', depth, false)?,
+            Token::Punctuation(_) => return None,
+        }
+        Some(())
+    }
+
+    fn container(&mut self, close: char, depth: usize, is_object: bool) -> Option<()> {
+        self.push(if is_object { "{" } else { "[" });
+        if self.peek_is(close) {
+            self.next();
+            self.push(&close.to_string());
+            return Some(());
+        }
+
+        loop {
+            self.new_line(depth + 1);
+            if is_object {
+                match self.next()? {
+                    Token::Value(key) if key.starts_with('"') => self.push(key),
+                    _ => return None,
+                }
+                if self.next()? != &Token::Punctuation(':') {
+                    return None;
+                }
+                self.push(": ");
+            }
+            self.value(depth + 1)?;
+
+            match self.next()? {
+                Token::Punctuation(',') => self.push(","),
+                Token::Punctuation(c) if *c == close => break,
+                _ => return None,
+            }
+        }
+
+        self.new_line(depth);
+        self.push(&close.to_string());
+        Some(())
+    }
+}
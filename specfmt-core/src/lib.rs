@@ -0,0 +1,70 @@
+//! The formatting core of specfmt, which formats Bikeshed and Wattsi
+//! specifications using WHATWG conventions. Unlike the `specfmt` command line
+//! tool, this depends on neither clap nor git, so it can be embedded in other
+//! tools (bots, editor plugins, Bikeshed wrappers) and built for targets like
+//! WebAssembly.
+//!
+//! Formatting a spec means splitting it into [`Line`]s, marking the ones to
+//! format, and passing them to [`rewrapper::rewrap_lines()`]:
+//!
+//! ```
+//! use specfmt_core::rewrapper::{self, RewrapOptions};
+//! use specfmt_core::Line;
+//!
+//! let spec = "<p>A paragraph that\nwas wrapped early.";
+//! let lines: Vec<Line> = spec
+//!     .split('\n')
+//!     .map(|contents| Line {
+//!         should_format: true,
+//!         contents,
+//!     })
+//!     .collect();
+//! let total_lines = lines.len();
+//! let rewrapped = rewrapper::rewrap_lines(lines, total_lines, &RewrapOptions::default());
+//! assert_eq!(rewrapped.lines, ["<p>A paragraph that was wrapped early."]);
+//! assert!(rewrapped.report.warnings.is_empty());
+//! ```
+//!
+//! [`diff`] and [`sections`] describe the changes formatting made, and [`json`]
+//! is an optional pass that pretty-prints JSON examples.
+
+mod cjk;
+pub mod diff;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+mod html;
+pub mod json;
+mod optimal;
+pub mod rewrapper;
+pub mod sections;
+
+/// Formats all of `spec` according to `options`. This is what tools without a
+/// notion of which lines changed (like editors formatting a buffer) want.
+///
+/// ```
+/// use specfmt_core::rewrapper::RewrapOptions;
+///
+/// let rewrapped = specfmt_core::format("<p>A\nparagraph.", &RewrapOptions::default());
+/// assert_eq!(rewrapped.lines.join("\n"), "<p>A paragraph.");
+/// ```
+pub fn format(spec: &str, options: &rewrapper::RewrapOptions) -> rewrapper::Rewrapped {
+    let lines: Vec<Line> = spec
+        .split('\n')
+        .map(|contents| Line {
+            should_format: true,
+            contents,
+        })
+        .collect();
+    let total_lines = lines.len();
+    rewrapper::rewrap_lines(lines, total_lines, options)
+}
+
+/// A simple struct that we use to track each line of the source specification.
+/// When scoping our reformatting changes to lines in a `git diff`, lines in the
+/// spec do not also appear in the diff will have `should_format = false`. We
+/// dynamically make other lines exempt from formatting based on other exceptions
+/// and rules as well.
+pub struct Line<'a> {
+    pub should_format: bool,
+    pub contents: &'a str,
+}
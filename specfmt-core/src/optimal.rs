@@ -0,0 +1,63 @@
+// Optimal line breaking, in the style of Knuth and Plass: instead of filling
+// each line as full as it goes, like `wrap_single_line()` does by default, it
+// picks the breaks that minimize the raggedness of the right margin across the
+// whole paragraph, measured as the sum of the squares of the space left at the
+// end of every line but the last.
+//
+// This only chooses where to break between words, which are never split. A
+// word too wide for any line gets a line of its own, at no cost.
+
+// Returns the indices of the words of `widths` that start each line after the
+// first, when the first line has `first_width` columns for words and the rest
+// have `width` columns each. Words on the same line are separated by a space.
+pub fn breaks(widths: &[usize], first_width: usize, width: usize) -> Vec<usize> {
+    let n = widths.len();
+    // `offsets[i]` is the width of the first `i` words, each followed by a
+    // space.
+    let mut offsets = vec![0; n + 1];
+    for (i, width) in widths.iter().enumerate() {
+        offsets[i + 1] = offsets[i] + width + 1;
+    }
+
+    // The lowest cost of a line starting with word `start`, with `available`
+    // columns, followed by the lines costing `rest(end)` after it when it ends
+    // before word `end`, along with that `end`.
+    let cheapest = |start: usize, available: usize, rest: &dyn Fn(usize) -> u64| {
+        let mut cheapest: Option<(u64, usize)> = None;
+        for end in (start + 1)..=n {
+            let used = offsets[end] - offsets[start] - 1;
+            if used > available && end - start > 1 {
+                break;
+            }
+            let cost = if end == n || used > available {
+                0
+            } else {
+                let slack = (available - used) as u64;
+                slack * slack
+            };
+            let cost = cost + rest(end);
+            if cheapest.is_none_or(|(lowest, _)| cost < lowest) {
+                cheapest = Some((cost, end));
+            }
+        }
+        cheapest
+    };
+
+    // `best[start]` is the lowest cost of putting the words from `start` on,
+    // on lines after the first, along with where the line starting at `start`
+    // ends.
+    let mut best: Vec<(u64, usize)> = vec![(0, n); n + 1];
+    for start in (1..n).rev() {
+        best[start] = cheapest(start, width, &|end| best[end].0).unwrap();
+    }
+    let Some((_, mut end)) = cheapest(0, first_width, &|end| best[end].0) else {
+        return Vec::new();
+    };
+
+    let mut breaks = Vec::new();
+    while end < n {
+        breaks.push(end);
+        end = best[end].1;
+    }
+    breaks
+}
@@ -0,0 +1,1948 @@
+//! Unwraps the paragraphs of a spec and rewraps them to a column length, which
+//! is the heart of the formatter.
+
+use super::cjk;
+use super::diff;
+use super::html;
+use super::optimal;
+use super::Line;
+use lazy_static::lazy_static;
+use regex::Regex;
+use unicode_width::UnicodeWidthChar;
+
+// A struct similar to `Line`, with the exception that `OwnedLine` does not
+// maintain a string reference, but rather an owned `String`. We cannot easily
+// keep a reference to the original spec strings, because due to unwrapping,
+// some of the lines of a spec have been mutated beyond the capability of
+// slicing.
+//
+// That is, when turn `LINE + NEW_LINE + LINE2` into `LINE + SPACE + LINE2`, we
+// are incapable of taking a slice over the entire line since it would include
+// two non-contiguous slices separated by a brand new space character. We could
+// modify `Line` to support this case where a given "line" consists of multiple
+// string slices and owned string spaces, for efficiency, but for now we just use
+// `OwnedLine` since it is easier.
+struct OwnedLine {
+    should_format: bool,
+    contents: String,
+}
+
+/// The result of rewrapping a spec.
+pub struct Rewrapped {
+    pub lines: Vec<String>,
+    pub report: FormatReport,
+}
+
+/// Describes what rewrapping did to a spec, for the caller to present however
+/// it sees fit. Line numbers are 0-based.
+pub struct FormatReport {
+    pub total_lines: usize,
+    pub lines_to_format: usize,
+    /// The number of lines of the original spec that rewrapping changed.
+    pub lines_changed: usize,
+    /// The ranges (inclusive) of lines that were subject to formatting, but
+    /// left alone because they are exempt from it, e.g., because they are in a
+    /// `<pre>` block or a `<!-- specfmt-off -->` region.
+    pub exempt_lines: Vec<(usize, usize)>,
+    pub warnings: Vec<Warning>,
+    /// The column length that lines were wrapped to, or 0 if there was no
+    /// limit.
+    pub column_length: usize,
+}
+
+pub enum Warning {
+    /// An exempt block opened on `line` of the original spec by `tag` (like
+    /// `<pre` or a "```" code fence) was never closed, so everything after it
+    /// is exempt from formatting.
+    UnclosedExemptBlock { tag: String, line: usize },
+    /// A `line` of the rewrapped spec that was subject to formatting is still
+    /// longer than the column length, e.g., because it contains a single word
+    /// that is too long to fit on any line.
+    Overflow { line: usize },
+}
+
+impl FormatReport {
+    /// The lines of the rewrapped spec that are still too long.
+    pub fn overflowing_lines(&self) -> Vec<usize> {
+        self.warnings
+            .iter()
+            .filter_map(|warning| match warning {
+                Warning::Overflow { line } => Some(*line),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The narrowest column length, other than 0 (no limit), worth wrapping to.
+pub const MIN_COLUMN_LENGTH: usize = 20;
+
+/// The width of a tab in spec source, unless configured otherwise. Bikeshed
+/// treats a tab as one level of indentation, which is 4 spaces by default.
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// Options for `rewrap_lines()`. Start from `RewrapOptions::default()`, which
+/// follows the WHATWG conventions, and override options with the builder
+/// methods:
+///
+/// ```
+/// use specfmt_core::rewrapper::{ContinuationIndent, RewrapOptions};
+///
+/// let options = RewrapOptions::default()
+///     .column_length(80)
+///     .continuation_indent(ContinuationIndent::Fixed(4));
+/// assert_eq!(options.column_length, 80);
+/// ```
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct RewrapOptions {
+    /// The column to wrap lines at, or 0 to join paragraphs into single lines
+    /// without ever splitting them. Column lengths narrower than
+    /// `MIN_COLUMN_LENGTH` leave little more than a word on each line, so
+    /// callers should reject them.
+    pub column_length: usize,
+    /// Whether to break overlong tags between their attributes, aligning
+    /// continuation lines under the first attribute.
+    pub wrap_attributes: bool,
+    /// Tags (like `"dfn"`) that keep the line break before them instead of
+    /// being joined onto the previous line.
+    pub preserve_breaks_before: Vec<String>,
+    /// Where to put the content of paragraphs relative to their `<p>` tag, if
+    /// anywhere in particular.
+    pub paragraph_content: Option<ParagraphContent>,
+    /// How to indent the continuation lines of wrapped lines.
+    pub continuation_indent: ContinuationIndent,
+    /// How to indent the continuation lines of wrapped lines that start with
+    /// particular markers, instead of by `continuation_indent`. Later rules
+    /// for the same marker override earlier ones.
+    pub continuation_rules: Vec<ContinuationRule>,
+    /// How far formatting reaches beyond the lines whose `should_format` is
+    /// true, if anywhere in particular.
+    pub scope: Option<Scope>,
+    /// Tags (like `"css-example"`) of elements whose content is exempt from
+    /// formatting, in addition to the built-in ones like `<pre>`.
+    pub exempt_tags: Vec<String>,
+    /// Tags (like `"span"`) of inline elements that are kept on one line, from
+    /// their open tag through their end tag, whenever they fit on one.
+    pub atomic_elements: Vec<String>,
+    /// How to choose where to break wrapped lines.
+    pub wrap_algorithm: WrapAlgorithm,
+    /// Whether to keep the line break after a line that ends a sentence, for
+    /// specs written with one sentence per line. Lines too long to fit are
+    /// still wrapped.
+    pub respect_sentence_breaks: bool,
+    /// Whether to join the lines of paragraphs before rewrapping them. Without
+    /// it, the only change is splitting lines that are too long.
+    pub unwrap_paragraphs: bool,
+    /// Whether to break lines between Chinese and Japanese characters (other
+    /// than where kinsoku rules forbid it), rather than only at spaces, which
+    /// that text doesn't have between words. Lines that end and start with
+    /// such characters are joined without a space.
+    pub break_cjk: bool,
+    /// Whether to measure each HTML character reference (like `&amp;`,
+    /// `&nbsp;`, or `&#x2014;`) as the width of the character it renders as,
+    /// rather than of its source, so that lines full of them aren't wrapped
+    /// before they look full. References are never broken either way.
+    pub rendered_entity_width: bool,
+    /// The number of columns between tab stops, which a tab advances to the
+    /// next of. It must be at least 1.
+    pub tab_width: usize,
+    /// Whether to replace the tabs in the indentation of the lines being
+    /// formatted with spaces, up to the same tab stops, so that wrapping never
+    /// carries them onto continuation lines. Tabs elsewhere, like in `<pre>`
+    /// blocks, are left alone.
+    pub expand_tabs: bool,
+    /// Whether to remove the spaces and tabs at the ends of the lines being
+    /// formatted. Lines outside the scope of formatting, or in exempt blocks,
+    /// keep theirs.
+    pub strip_trailing_whitespace: bool,
+    /// Whether to replace the indentation of the lines being formatted with
+    /// one space for each element they're nested in, as the HTML Standard
+    /// does, instead of keeping it. Only block containers like `<div>`, `<ol>`,
+    /// `<li>`, and `<dd>` count, and a `<li>`, `<dt>`, or `<dd>` closes the one
+    /// before it even without an end tag. Markdown list items and blockquotes
+    /// keep their indentation, which is part of their markup.
+    pub reindent: bool,
+    /// Whether the spec uses Bikeshed's markdown, whose code fences, tables,
+    /// headings, list items, and blockquotes are kept apart from the prose
+    /// around them. Without it, lines that merely look like markdown (like one
+    /// starting with `- `) are just prose.
+    pub markdown: bool,
+}
+
+impl Default for RewrapOptions {
+    fn default() -> Self {
+        RewrapOptions {
+            column_length: 100,
+            wrap_attributes: false,
+            preserve_breaks_before: Vec::new(),
+            paragraph_content: None,
+            continuation_indent: ContinuationIndent::Marker,
+            continuation_rules: Vec::new(),
+            scope: None,
+            exempt_tags: Vec::new(),
+            atomic_elements: Vec::new(),
+            wrap_algorithm: WrapAlgorithm::Greedy,
+            respect_sentence_breaks: false,
+            unwrap_paragraphs: true,
+            break_cjk: false,
+            rendered_entity_width: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            expand_tabs: false,
+            strip_trailing_whitespace: false,
+            reindent: false,
+            markdown: true,
+        }
+    }
+}
+
+impl RewrapOptions {
+    pub fn column_length(mut self, column_length: usize) -> Self {
+        self.column_length = column_length;
+        self
+    }
+
+    pub fn wrap_attributes(mut self, wrap_attributes: bool) -> Self {
+        self.wrap_attributes = wrap_attributes;
+        self
+    }
+
+    pub fn preserve_breaks_before(mut self, tags: Vec<String>) -> Self {
+        self.preserve_breaks_before = tags;
+        self
+    }
+
+    pub fn paragraph_content(mut self, paragraph_content: ParagraphContent) -> Self {
+        self.paragraph_content = Some(paragraph_content);
+        self
+    }
+
+    pub fn continuation_indent(mut self, continuation_indent: ContinuationIndent) -> Self {
+        self.continuation_indent = continuation_indent;
+        self
+    }
+
+    pub fn continuation_rules(mut self, rules: Vec<ContinuationRule>) -> Self {
+        self.continuation_rules = rules;
+        self
+    }
+
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    pub fn exempt_tags(mut self, tags: Vec<String>) -> Self {
+        self.exempt_tags = tags;
+        self
+    }
+
+    pub fn atomic_elements(mut self, tags: Vec<String>) -> Self {
+        self.atomic_elements = tags;
+        self
+    }
+
+    pub fn wrap_algorithm(mut self, wrap_algorithm: WrapAlgorithm) -> Self {
+        self.wrap_algorithm = wrap_algorithm;
+        self
+    }
+
+    pub fn respect_sentence_breaks(mut self, respect_sentence_breaks: bool) -> Self {
+        self.respect_sentence_breaks = respect_sentence_breaks;
+        self
+    }
+
+    pub fn unwrap_paragraphs(mut self, unwrap_paragraphs: bool) -> Self {
+        self.unwrap_paragraphs = unwrap_paragraphs;
+        self
+    }
+
+    pub fn break_cjk(mut self, break_cjk: bool) -> Self {
+        self.break_cjk = break_cjk;
+        self
+    }
+
+    pub fn rendered_entity_width(mut self, rendered_entity_width: bool) -> Self {
+        self.rendered_entity_width = rendered_entity_width;
+        self
+    }
+
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    pub fn expand_tabs(mut self, expand_tabs: bool) -> Self {
+        self.expand_tabs = expand_tabs;
+        self
+    }
+
+    pub fn strip_trailing_whitespace(mut self, strip_trailing_whitespace: bool) -> Self {
+        self.strip_trailing_whitespace = strip_trailing_whitespace;
+        self
+    }
+
+    pub fn reindent(mut self, reindent: bool) -> Self {
+        self.reindent = reindent;
+        self
+    }
+
+    pub fn markdown(mut self, markdown: bool) -> Self {
+        self.markdown = markdown;
+        self
+    }
+
+    // How to measure the width of lines.
+    fn measure(&self) -> Measure {
+        Measure {
+            tab_width: self.tab_width.max(1),
+            rendered_entity_width: self.rendered_entity_width,
+        }
+    }
+}
+
+/// How far formatting reaches beyond the lines that are subject to it, like the
+/// lines changed on a branch. By default, a line subject to formatting is
+/// joined onto the line before it, which becomes subject to formatting too.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Scope {
+    /// Only the lines subject to formatting are reflowed, among themselves.
+    Line,
+    /// The whole paragraphs enclosing them are reflowed.
+    Paragraph,
+    /// The whole blocks enclosing them, up to the nearest blank lines, are
+    /// reflowed.
+    Block,
+}
+
+/// How to choose where to break wrapped lines.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum WrapAlgorithm {
+    /// Fit as many words as possible on each line before moving on to the next.
+    /// A change to a paragraph only rewraps it from the changed line on.
+    Greedy,
+    /// Break lines where it minimizes the raggedness of the right margin
+    /// across the whole paragraph, at the cost of changes to a paragraph
+    /// rewrapping all of it.
+    Optimal,
+}
+
+/// Unwraps the paragraphs of `lines` and rewraps them according to `options`,
+/// leaving lines whose `should_format` is false alone (other than joining
+/// lines onto them). `diff_lines` is the number of lines that formatting is
+/// scoped to, which is only used for the report.
+pub fn rewrap_lines(mut lines: Vec<Line>, diff_lines: usize, options: &RewrapOptions) -> Rewrapped {
+    let total_lines = lines.len();
+    let original = lines
+        .iter()
+        .map(|line| line.contents)
+        .collect::<Vec<&str>>()
+        .join("\n");
+
+    if let Some(scope) = options.scope {
+        extend_scope(&mut lines, scope);
+    }
+    let in_scope: Vec<bool> = lines.iter().map(|line| line.should_format).collect();
+    let mut warnings = exempt_blocks(&mut lines, &options.exempt_tags, options.markdown);
+    warnings.extend(exempt_directives(&mut lines));
+    if options.markdown {
+        exempt_tables(&mut lines);
+    }
+    if options.strip_trailing_whitespace {
+        for line in lines.iter_mut().filter(|line| line.should_format) {
+            line.contents = line.contents.trim_end_matches([' ', '\t']);
+        }
+    }
+    let mut exempt_lines = Vec::<(usize, usize)>::new();
+    for (i, line) in lines.iter().enumerate() {
+        if !in_scope[i] || line.should_format {
+            continue;
+        }
+        match exempt_lines.last_mut() {
+            Some((_, end)) if *end + 1 == i => *end = i,
+            _ => exempt_lines.push((i, i)),
+        }
+    }
+    let mut unwrapped_lines: Vec<OwnedLine> = if options.unwrap_paragraphs {
+        unwrap_lines(
+            lines,
+            &options.preserve_breaks_before,
+            options.respect_sentence_breaks,
+            options.break_cjk,
+            options.markdown,
+            options.scope == Some(Scope::Line),
+        )
+    } else {
+        lines
+            .into_iter()
+            .map(|line| OwnedLine {
+                should_format: line.should_format,
+                contents: line.contents.to_string(),
+            })
+            .collect()
+    };
+    if options.reindent {
+        reindent(&mut unwrapped_lines, options.markdown);
+    }
+    if options.expand_tabs {
+        for line in unwrapped_lines.iter_mut().filter(|line| line.should_format) {
+            line.contents = expand_indentation_tabs(&line.contents, options.measure());
+        }
+    }
+    if let Some(paragraph_content) = options.paragraph_content {
+        unwrapped_lines =
+            place_paragraph_content(unwrapped_lines, paragraph_content, options.markdown);
+    }
+    let (rewrapped_lines, overflowing_lines) = wrap_lines(unwrapped_lines, options);
+    warnings.extend(
+        overflowing_lines
+            .into_iter()
+            .map(|line| Warning::Overflow { line }),
+    );
+
+    let lines_changed = diff::hunks(&original, &rewrapped_lines.join("\n"))
+        .iter()
+        .flat_map(|hunk| hunk.lines.iter())
+        .filter(|line| line.starts_with('-'))
+        .count();
+
+    Rewrapped {
+        lines: rewrapped_lines,
+        report: FormatReport {
+            total_lines,
+            lines_to_format: diff_lines,
+            lines_changed,
+            exempt_lines,
+            warnings,
+            column_length: options.column_length,
+        },
+    }
+}
+
+// The elements whose content is exempt from formatting, because its whitespace
+// is significant (like `<pre>` or `<textarea>`), it isn't prose (like
+// `<script>` or `<template>`), or it is laid out by hand (like `<table>` or
+// `<math>`).
+const EXEMPT_TAGS: [&str; 11] = [
+    "pre",
+    "xmp",
+    "listing",
+    "plaintext",
+    "textarea",
+    "style",
+    "script",
+    "template",
+    "svg",
+    "math",
+    "table",
+];
+
+lazy_static! {
+    // A Bikeshed markdown code fence: three or more backticks or tildes, and an
+    // optional info string (like "js") that can't contain backticks after a
+    // backtick fence.
+    static ref CODE_FENCE: Regex = Regex::new(r"^\s*(`{3,}[^`]*|~{3,}.*)$").unwrap();
+}
+
+// This function exempts all of the lines appearing inside various blocks (those
+// of `EXEMPT_TAGS` and `exempt_tags`), from the line that opens one through the
+// line that closes it. Comments that span lines, CDATA sections, and processing
+// instructions are exempt too. Blocks can be nested (like a `<table>` in a
+// `<table>`, or a comment in a `<pre>`), so we track the open ones on a stack,
+// and only leave the outermost one at its own end tag. A single line can open
+// and close several blocks (like `<pre>a</pre> prose <pre>`), so we follow all
+// of its tags, in order, to know whether we're in a block after it.
+//
+// With `markdown`, code fences (like "```js" through "```") outside of any
+// block are exempt along with their content, which isn't tokenized as HTML.
+// Warns about a block or fence that is never closed.
+fn exempt_blocks(lines: &mut Vec<Line>, exempt_tags: &[String], markdown: bool) -> Vec<Warning> {
+    let is_exempt = |name: &str| {
+        EXEMPT_TAGS.contains(&name) || exempt_tags.iter().any(|tag| tag.eq_ignore_ascii_case(name))
+    };
+    let mut tokenizer = html::Tokenizer::default();
+    // The tags (like "<pre") of the exempt blocks we're in, outermost first.
+    let mut open_blocks: Vec<String> = Vec::new();
+    let mut block_start = 0;
+    // The fence (like "```") of the code block we're in, if any.
+    let mut open_fence: Option<String> = None;
+    for (i, line) in lines.iter_mut().enumerate() {
+        if let Some(fence) = &open_fence {
+            line.should_format = false;
+            if closes_fence(line.contents, fence) {
+                open_fence = None;
+            }
+            continue;
+        }
+        if markdown
+            && open_blocks.is_empty()
+            && tokenizer.in_text()
+            && CODE_FENCE.is_match(line.contents)
+        {
+            line.should_format = false;
+            let marker = line.contents.trim_start();
+            let fence_char = marker.chars().next().unwrap();
+            open_fence = Some(marker.chars().take_while(|&c| c == fence_char).collect());
+            block_start = i;
+            continue;
+        }
+
+        let mut exempt = !open_blocks.is_empty();
+        for token in tokenizer.tokens(line.contents) {
+            let was_in_block = !open_blocks.is_empty();
+            match token {
+                html::Token::StartTag(name) if is_exempt(&name) => {
+                    open_blocks.push(format!("<{}", name));
+                }
+                html::Token::CommentStart => open_blocks.push(String::from("<!--")),
+                html::Token::CdataStart => open_blocks.push(String::from("<![CDATA[")),
+                html::Token::ProcessingInstructionStart => open_blocks.push(String::from("<?")),
+                // An end tag also closes any blocks opened inside of its
+                // element that are still open, like HTML parsers do.
+                html::Token::EndTag(name) => {
+                    match open_blocks.iter().rposition(|tag| tag[1..] == name) {
+                        Some(depth) => open_blocks.truncate(depth),
+                        None => continue,
+                    }
+                }
+                html::Token::CommentEnd if open_blocks.last().is_some_and(|tag| tag == "<!--") => {
+                    open_blocks.pop();
+                    // A comment that opens and closes on this line is inline,
+                    // like a word of prose. (One opened on an earlier line has
+                    // already made this line exempt.)
+                    if open_blocks.is_empty() {
+                        continue;
+                    }
+                }
+                html::Token::CdataEnd
+                    if open_blocks.last().is_some_and(|tag| tag == "<![CDATA[") =>
+                {
+                    open_blocks.pop();
+                }
+                html::Token::ProcessingInstructionEnd
+                    if open_blocks.last().is_some_and(|tag| tag == "<?") =>
+                {
+                    open_blocks.pop();
+                }
+                _ => continue,
+            }
+            if !was_in_block {
+                block_start = i;
+                if open_blocks.last().is_some_and(|tag| tag == "<!--") {
+                    continue;
+                }
+            }
+            exempt = true;
+        }
+
+        // If we're in an exempt block, mark the line as exempt from formatting.
+        if exempt || !open_blocks.is_empty() {
+            line.should_format = false;
+        }
+    }
+
+    if let Some(fence) = open_fence {
+        return vec![Warning::UnclosedExemptBlock {
+            tag: fence,
+            line: block_start,
+        }];
+    }
+    match open_blocks.into_iter().next() {
+        // `<plaintext>` has no end tag, and is meant to run to the end of the
+        // spec.
+        Some(tag) if tag == "<plaintext" => Vec::new(),
+        Some(tag) => vec![Warning::UnclosedExemptBlock {
+            tag,
+            line: block_start,
+        }],
+        None => Vec::new(),
+    }
+}
+
+// Whether `line` closes the code block opened by `fence`, which takes a fence of
+// the same character that is at least as long, with nothing after it.
+fn closes_fence(line: &str, fence: &str) -> bool {
+    let line = line.trim();
+    let fence_char = fence.chars().next().unwrap();
+    line.len() >= fence.len() && line.chars().all(|c| c == fence_char)
+}
+
+lazy_static! {
+    static ref SPECFMT_OFF: Regex = Regex::new(r"<!--\s*specfmt-off\s*-->").unwrap();
+    static ref SPECFMT_ON: Regex = Regex::new(r"<!--\s*specfmt-on\s*-->").unwrap();
+    static ref SPECFMT_IGNORE_NEXT_LINE: Regex =
+        Regex::new(r"<!--\s*specfmt-ignore-next-line\s*-->").unwrap();
+}
+
+// Exempts the lines from a `<!-- specfmt-off -->` comment through the next
+// `<!-- specfmt-on -->` comment, which authors use to protect hand-formatted
+// prose, as well as the line after a `<!-- specfmt-ignore-next-line -->`
+// comment. Warns about a region that is never turned back on.
+fn exempt_directives(lines: &mut [Line]) -> Vec<Warning> {
+    let mut region_start: Option<usize> = None;
+    let mut ignore_line = false;
+    for (i, line) in lines.iter_mut().enumerate() {
+        if ignore_line {
+            line.should_format = false;
+        }
+        ignore_line = SPECFMT_IGNORE_NEXT_LINE.is_match(line.contents);
+        if ignore_line {
+            line.should_format = false;
+        }
+
+        if region_start.is_none() && SPECFMT_OFF.is_match(line.contents) {
+            region_start = Some(i);
+        }
+        if region_start.is_some() {
+            line.should_format = false;
+            if SPECFMT_ON.is_match(line.contents) {
+                region_start = None;
+            }
+        }
+    }
+
+    match region_start {
+        Some(line) => vec![Warning::UnclosedExemptBlock {
+            tag: String::from("<!-- specfmt-off -->"),
+            line,
+        }],
+        None => Vec::new(),
+    }
+}
+
+lazy_static! {
+    // The cells of the row under the header of a markdown table, like
+    // "| --- | :-: |", which must also contain a `|` to tell it apart from a
+    // thematic break.
+    static ref TABLE_DELIMITER_ROW: Regex =
+        Regex::new(r"^\s*\|?\s*:?-+:?\s*(\|\s*:?-+:?\s*)*\|?\s*$").unwrap();
+}
+
+// Exempts markdown tables, whose rows are lines with cells separated by `|`.
+// A table starts with a header row followed by a delimiter row, and runs
+// through the next blank line, like in GitHub Flavored Markdown, where even a
+// following line without a `|` is a row.
+fn exempt_tables(lines: &mut [Line]) {
+    let is_row = |line: &Line| line.contents.contains('|');
+    let mut i = 1;
+    while i < lines.len() {
+        let is_delimiter = is_row(&lines[i]) && TABLE_DELIMITER_ROW.is_match(lines[i].contents);
+        if !is_delimiter || !is_row(&lines[i - 1]) {
+            i += 1;
+            continue;
+        }
+        lines[i - 1].should_format = false;
+        while i < lines.len() && !lines[i].contents.trim().is_empty() {
+            lines[i].should_format = false;
+            i += 1;
+        }
+    }
+}
+
+// Helpers.
+lazy_static! {
+    static ref SINGLE_TAG: Regex = Regex::new(r#"^</?[a-z-A-Z "=]+>$"#).unwrap();
+    static ref FULL_DT_TAG: Regex = Regex::new(r#"<dt.*>.*</dt>$"#).unwrap();
+    static ref HEADER_TAG: Regex = Regex::new(r#"<h[0-6].*>.*</h[0-6]>$"#).unwrap();
+    // Matches a markdown ATX heading, like `## Heading {#id}`.
+    static ref MARKDOWN_HEADING: Regex = Regex::new(r"^#{1,6}(\s|$)").unwrap();
+    // Matches the line under a markdown setext heading (`===` or `---`), which
+    // is also a thematic break when it's `---`.
+    static ref SETEXT_UNDERLINE: Regex = Regex::new(r"^(=+|-+)$").unwrap();
+    // Matches the marker of a markdown numbered list item (`1. ` or `1) `) or
+    // bulleted list item (`* `, `- `, or `+ `), or of a Bikeshed definition
+    // list term (`: `) or definition (`:: `), along with the spaces that
+    // follow it.
+    static ref LIST_MARKER: Regex = Regex::new(r"^(\d+[.)]|[*+-]|::?)[ \t]+").unwrap();
+    // Matches the indentation and `>` markers (along with the spaces after
+    // them) that start a line of a markdown blockquote, like `  > > `.
+    static ref QUOTE_PREFIX: Regex = Regex::new(r"^[ \t]*(>[ \t]*)+").unwrap();
+}
+
+// The `QUOTE_PREFIX` of `line`, or "" if it isn't in a blockquote.
+fn quote_prefix(line: &str) -> &str {
+    QUOTE_PREFIX.find(line).map_or("", |prefix| prefix.as_str())
+}
+// `line` without its `QUOTE_PREFIX`.
+fn unquoted(line: &str) -> &str {
+    &line[quote_prefix(line).len()..]
+}
+// How many blockquotes `line` is nested in. Lines are only ever joined with
+// lines of the same depth, so quotes never absorb the prose around them.
+fn quote_depth(line: &str) -> usize {
+    quote_prefix(line).matches('>').count()
+}
+
+// How to measure the width of text.
+#[derive(Clone, Copy)]
+struct Measure {
+    tab_width: usize,
+    rendered_entity_width: bool,
+}
+
+lazy_static! {
+    // Matches an HTML character reference, like `&amp;`, `&#8212;`, or
+    // `&#x2014;`, capturing the code point of a numeric one.
+    static ref ENTITY: Regex =
+        Regex::new(r"^&(?:#([0-9]{1,7})|#[xX]([0-9a-fA-F]{1,6})|[a-zA-Z][a-zA-Z0-9]*);").unwrap();
+}
+
+impl Measure {
+    // The number of columns `text` takes up at the start of a line, as an
+    // editor displays it: wide characters (like most CJK ones) take up two
+    // columns, combining marks and other zero-width characters none, and tabs
+    // advance to the next tab stop. With `rendered_entity_width`, a character
+    // reference takes up as many columns as the character it renders as (or
+    // one, for a named one), rather than one per character of its source.
+    fn width(self, text: &str) -> usize {
+        let mut width = 0;
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            let entity = (self.rendered_entity_width && c == '&')
+                .then(|| ENTITY.captures(rest))
+                .flatten();
+            if let Some(entity) = entity {
+                let code_point = entity
+                    .get(1)
+                    .and_then(|decimal| decimal.as_str().parse().ok())
+                    .or_else(|| {
+                        entity
+                            .get(2)
+                            .and_then(|hex| u32::from_str_radix(hex.as_str(), 16).ok())
+                    });
+                width += code_point.and_then(char::from_u32).map_or(1, char_width);
+                rest = &rest[entity[0].len()..];
+                continue;
+            }
+            width = match c {
+                '\t' => (width / self.tab_width + 1) * self.tab_width,
+                c => width + char_width(c),
+            };
+            rest = &rest[c.len_utf8()..];
+        }
+        width
+    }
+}
+
+// The number of columns `c` takes up, other than a tab.
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+// Whether `line` is a single comment, like `<!-- TODO: ... -->`.
+fn is_comment(line: &str) -> bool {
+    line.starts_with("<!--") && line.find("-->") == Some(line.len() - 3)
+}
+fn is_standalone_line(line: &str, markdown: bool) -> bool {
+    line.is_empty()
+        || is_comment(line)
+        || SINGLE_TAG.is_match(line)
+        || FULL_DT_TAG.is_match(line)
+        || HEADER_TAG.is_match(line)
+        || (markdown
+            && (MARKDOWN_HEADING.is_match(line)
+                || SETEXT_UNDERLINE.is_match(line)
+                || CODE_FENCE.is_match(line)))
+}
+// Whether unwrapping would join `line` onto the `previous` one of a spec that
+// uses markdown, if both are subject to formatting (and ignoring
+// `preserve_breaks_before`).
+fn joinable(previous: &str, line: &str) -> bool {
+    !is_standalone_line(unquoted(previous).trim(), true)
+        && !must_break(previous, false)
+        && !is_standalone_line(unquoted(line).trim(), true)
+        && !must_start_on_new_line(unquoted(line), true, false)
+        && quote_depth(previous) == quote_depth(line)
+}
+/// Returns the 0-based, inclusive range of lines spanned by the paragraphs that
+/// enclose lines `start` to `end` of `lines`, i.e., the lines that unwrapping
+/// would join them with.
+pub fn enclosing_paragraphs(lines: &[Line], mut start: usize, mut end: usize) -> (usize, usize) {
+    while start > 0 && joinable(lines[start - 1].contents, lines[start].contents) {
+        start -= 1;
+    }
+    while end + 1 < lines.len() && joinable(lines[end].contents, lines[end + 1].contents) {
+        end += 1;
+    }
+    (start, end)
+}
+
+// The column lengths that `detect_column_length()` chooses between, which are
+// the WHATWG convention and the most common other one.
+const DETECTABLE_COLUMN_LENGTHS: [usize; 2] = [80, 100];
+
+/// Guesses the column length that `spec` was wrapped to, by counting the lines
+/// of its paragraphs that end where they do because the next word wouldn't fit
+/// within each of the usual column lengths (80 and 100). Returns `None` if no
+/// line gives that away, like in a spec without multi-line paragraphs.
+///
+/// ```
+/// use specfmt_core::rewrapper;
+///
+/// let spec = format!("<p>{}\nthe rest of the paragraph.", "word ".repeat(15).trim());
+/// assert_eq!(rewrapper::detect_column_length(&spec), Some(80));
+/// ```
+pub fn detect_column_length(spec: &str) -> Option<usize> {
+    let mut lines: Vec<Line> = spec
+        .split('\n')
+        .map(|contents| Line {
+            should_format: true,
+            contents,
+        })
+        .collect();
+    exempt_blocks(&mut lines, &[], true);
+    exempt_directives(&mut lines);
+    exempt_tables(&mut lines);
+
+    let measure = RewrapOptions::default().measure();
+    let mut votes = [0; DETECTABLE_COLUMN_LENGTHS.len()];
+    for pair in lines.windows(2) {
+        let (line, next) = (&pair[0], &pair[1]);
+        if !line.should_format || !next.should_format || !joinable(line.contents, next.contents) {
+            continue;
+        }
+        let Some(next_word) = unquoted(next.contents).split_whitespace().next() else {
+            continue;
+        };
+        let line_width = measure.width(line.contents.trim_end());
+        for (votes, column_length) in votes.iter_mut().zip(DETECTABLE_COLUMN_LENGTHS) {
+            if line_width <= column_length
+                && line_width + 1 + measure.width(next_word) > column_length
+            {
+                *votes += 1;
+            }
+        }
+    }
+    // Ties go to the widest column length, the default.
+    let (column_length, votes) = DETECTABLE_COLUMN_LENGTHS
+        .into_iter()
+        .zip(votes)
+        .max_by_key(|(_, votes)| *votes)?;
+    (votes > 0).then_some(column_length)
+}
+
+// Makes the lines that `scope` reaches from the lines subject to formatting
+// subject to it too.
+fn extend_scope(lines: &mut [Line], scope: Scope) {
+    let mut i = 0;
+    while i < lines.len() {
+        if !lines[i].should_format {
+            i += 1;
+            continue;
+        }
+        let (start, end) = match scope {
+            Scope::Line => (i, i),
+            Scope::Paragraph => enclosing_paragraphs(lines, i, i),
+            Scope::Block => {
+                let blank = |line: &Line| line.contents.trim().is_empty();
+                let start = lines[..i].iter().rposition(blank).map_or(0, |j| j + 1);
+                let end = lines[i..]
+                    .iter()
+                    .position(blank)
+                    .map_or(lines.len(), |j| i + j);
+                (start, end.saturating_sub(1).max(i))
+            }
+        };
+        for line in &mut lines[start..=end] {
+            line.should_format = true;
+        }
+        i = end + 1;
+    }
+}
+
+// This differs from `is_standalone_line()` in that it is a weaker check. If
+// `is_standalone_line()` is true, then we prevent:
+//   (a): The current line from being appended to the end of earlier lines
+//   (b): Later lines from being appended to the end of the current line
+// If a given line isn't "standalone", it can be appended to a previous line,
+// but if `must_break()` is true, we prevent later lines from being appended to
+// the end of the current line. So `must_break()` is a strictly less-powerful
+// condition to gate behavior on. In a switch list (see `SwitchLists`), nothing
+// is appended to a `<dt>` line either, even when its end tag is omitted.
+fn must_break(line: &str, in_switch: bool) -> bool {
+    (in_switch && starts_with_switch_item(line, "dt"))
+        || line.ends_with("</li>")
+        || line.ends_with("</dt>")
+        || line.ends_with("</dd>")
+        || line.ends_with("-->")
+        || line.ends_with("]]>")
+        || line.ends_with("?>")
+}
+lazy_static! {
+    // Matches the end of a line that ends a sentence: sentence-final
+    // punctuation, possibly followed by closing quotes, brackets, or end tags,
+    // like `... is <code>null</code>.` or `(... a <dfn>foo</dfn>.)`.
+    static ref SENTENCE_END: Regex =
+        Regex::new(r#"[.!?]['"’”)\]]*(</[a-zA-Z][\w-]*>)*$"#).unwrap();
+    // Matches abbreviations that end in a period, but rarely end a sentence.
+    static ref ABBREVIATION: Regex = Regex::new(r#"(^|\W)(e\.g|i\.e|cf|vs)\.$"#).unwrap();
+}
+// Whether `line` ends a sentence, so that with `respect_sentence_breaks` the
+// next line isn't joined onto it.
+fn ends_sentence(line: &str) -> bool {
+    let line = line.trim_end();
+    SENTENCE_END.is_match(line) && !ABBREVIATION.is_match(line)
+}
+// Unlike HTML list items, markdown list items and definitions have nothing
+// closing them, so we break before each one instead of after. Neither does a
+// `<dt>` or `<dd>` in a switch list necessarily, so we break before those too.
+fn must_start_on_new_line(line: &str, markdown: bool, in_switch: bool) -> bool {
+    (markdown && LIST_MARKER.is_match(line.trim_start()))
+        || (in_switch
+            && (starts_with_switch_item(line, "dt") || starts_with_switch_item(line, "dd")))
+}
+// Whether `line` is left as it is, however long, like a `<dt>` in a switch
+// list.
+fn exempt_from_wrapping(line: &str, in_switch: bool) -> bool {
+    FULL_DT_TAG.is_match(line) || (in_switch && starts_with_switch_item(line, "dt"))
+}
+
+lazy_static! {
+    // Matches a `<dl>` open tag, capturing its attributes, or a `</dl>` end
+    // tag.
+    static ref DL_TAG: Regex = Regex::new(r"(?i)<dl(\s[^<>]*)?>|</dl\s*>").unwrap();
+    // Matches a `class` attribute, capturing its value.
+    static ref CLASS_ATTRIBUTE: Regex =
+        Regex::new(r#"(?i)\bclass\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap();
+    // Matches the `<dt>` or `<dd>` tag that starts a line, capturing its name.
+    static ref SWITCH_ITEM: Regex = Regex::new(r"^(?i)\s*<(dt|dd)[\s>]").unwrap();
+}
+
+// Tracks whether lines are in one of the HTML Standard's switch lists, a
+// `<dl class="switch">` whose `<dt>`s are conditions and whose `<dd>`s are the
+// steps to take when they hold. Each `<dt>` and `<dd>` starts a line of its own,
+// and the content of a `<dd>` is aligned after its tag.
+#[derive(Default)]
+struct SwitchLists {
+    // Whether each `<dl>` we're in is a switch list, outermost first.
+    open: Vec<bool>,
+}
+
+impl SwitchLists {
+    // Returns whether `line`, the line after the one previously passed to this,
+    // starts directly in a switch list.
+    fn advance(&mut self, line: &str) -> bool {
+        let in_switch = self.open.last().copied().unwrap_or(false);
+        for tag in DL_TAG.captures_iter(line) {
+            if tag[0].starts_with("</") {
+                self.open.pop();
+                continue;
+            }
+            let attributes = tag.get(1).map_or("", |attributes| attributes.as_str());
+            let is_switch = CLASS_ATTRIBUTE.captures(attributes).is_some_and(|class| {
+                (1..=3)
+                    .find_map(|i| class.get(i))
+                    .is_some_and(|value| value.as_str().split_whitespace().any(|c| c == "switch"))
+            });
+            self.open.push(is_switch);
+        }
+        in_switch
+    }
+}
+
+// Whether `line` starts with a `tag` (`"dt"` or `"dd"`) tag.
+fn starts_with_switch_item(line: &str, tag: &str) -> bool {
+    SWITCH_ITEM
+        .captures(line)
+        .is_some_and(|item| item[1].eq_ignore_ascii_case(tag))
+}
+// Whether `line` starts with an open tag for one of `tags`. Some editors start
+// certain elements (like `<dfn>`s or notes) on their own line on purpose, and
+// we preserve the line break before them.
+fn starts_with_tag(line: &str, tags: &[String]) -> bool {
+    tags.iter().any(|tag| {
+        match line
+            .strip_prefix('<')
+            .and_then(|line| line.strip_prefix(tag.as_str()))
+        {
+            Some(rest) => matches!(rest.chars().next(), None | Some(' ' | '>' | '/')),
+            None => false,
+        }
+    })
+}
+
+// TODO: This algorithm has a bug where if `git diff` describes an addition to a
+// line in a perfectly-formatted paragraph, such that the addition makes the
+// line now too long middle of a perfectly-formatted paragraph, we'll only
+// rewrap that line, which might leave subsequent lines sub-optimally wrapped
+// (too short). See https://github.com/domfarolino/specfmt/issues/8.
+//
+// With `respect_sentence_breaks`, lines are never joined onto a line that ends
+// a sentence. With `break_cjk`, lines that end and start with CJK characters
+// are joined without a space between them. Without `markdown`, markdown
+// headings, list items, and the like are joined like any other line. With
+// `only_in_scope`, lines are only joined onto lines that are subject to
+// formatting themselves, so that nothing else is reflowed.
+fn unwrap_lines(
+    lines: Vec<Line>,
+    preserve_breaks_before: &[String],
+    respect_sentence_breaks: bool,
+    break_cjk: bool,
+    markdown: bool,
+    only_in_scope: bool,
+) -> Vec<OwnedLine> {
+    let mut return_lines = Vec::<OwnedLine>::new();
+    let mut previous_line_smushable = false;
+    // A line ignored by a directive must stay exactly as it is, so nothing can
+    // be appended to it either.
+    let mut ignore_line = false;
+    let mut switch_lists = SwitchLists::default();
+
+    for line in lines {
+        let in_switch = switch_lists.advance(line.contents);
+        // The contents of a blockquote line are unwrapped like any other line,
+        // after its `>` markers.
+        let contents = if markdown {
+            unquoted(line.contents)
+        } else {
+            line.contents
+        };
+        if is_standalone_line(contents.trim(), markdown) {
+            return_lines.push(OwnedLine {
+                should_format: line.should_format,
+                contents: line.contents.to_string(),
+            });
+            previous_line_smushable = false;
+        } else {
+            if previous_line_smushable
+                && line.should_format
+                && !must_start_on_new_line(contents, markdown, in_switch)
+                && !starts_with_tag(contents.trim(), preserve_breaks_before)
+                && (!markdown
+                    || return_lines.last().is_some_and(|last| {
+                        quote_depth(&last.contents) == quote_depth(line.contents)
+                    }))
+                && (!only_in_scope || return_lines.last().is_some_and(|last| last.should_format))
+            {
+                assert_ne!(return_lines.len(), 0);
+                let n = return_lines.len();
+                // If we're unwrapping this line by tacking it onto the end of
+                // the previous one, we have to mark the previous line as a
+                // candidate for formatting (it might not already be).
+                return_lines[n - 1].should_format = true;
+                let cjk_join = break_cjk
+                    && return_lines[n - 1].contents.ends_with(cjk::is_cjk)
+                    && contents.trim().starts_with(cjk::is_cjk);
+                let separator = if cjk_join { "" } else { " " };
+                return_lines[n - 1]
+                    .contents
+                    .push_str(&(String::from(separator) + contents.trim()));
+            } else {
+                return_lines.push(OwnedLine {
+                    should_format: line.should_format,
+                    contents: line.contents.to_string(),
+                });
+            }
+
+            let sentence_break = respect_sentence_breaks && ends_sentence(line.contents);
+            previous_line_smushable =
+                !must_break(line.contents, in_switch) && !sentence_break && !ignore_line;
+        }
+        ignore_line = SPECFMT_IGNORE_NEXT_LINE.is_match(line.contents);
+    }
+
+    return_lines
+}
+
+/// Where the content of a paragraph goes relative to its `<p>` tag. WHATWG
+/// specs put it on the same line as the tag, but some projects prefer the tag
+/// on a line of its own.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ParagraphContent {
+    SameLine,
+    NextLine,
+}
+
+lazy_static! {
+    static ref BARE_P_TAG: Regex = Regex::new(r#"^\s*<p(?:\s[^<>]*)?>$"#).unwrap();
+    static ref P_TAG_WITH_CONTENT: Regex =
+        Regex::new(r#"^(\s*)(<p(?:\s[^<>]*)?>)\s*(\S.*)$"#).unwrap();
+}
+
+// Enforces the `paragraph_content` convention on the (unwrapped) lines that are
+// subject to formatting, by either joining a bare `<p>` line with the paragraph
+// text that follows it, or by splitting them apart.
+fn place_paragraph_content(
+    lines: Vec<OwnedLine>,
+    paragraph_content: ParagraphContent,
+    markdown: bool,
+) -> Vec<OwnedLine> {
+    let mut return_lines = Vec::<OwnedLine>::new();
+    for line in lines {
+        match paragraph_content {
+            ParagraphContent::SameLine => {
+                if let Some(previous) = return_lines.last_mut() {
+                    if previous.should_format
+                        && line.should_format
+                        && BARE_P_TAG.is_match(&previous.contents)
+                        && !is_standalone_line(line.contents.trim(), markdown)
+                    {
+                        previous.contents.push_str(line.contents.trim());
+                        continue;
+                    }
+                }
+                return_lines.push(line);
+            }
+            ParagraphContent::NextLine => {
+                let captures = match P_TAG_WITH_CONTENT.captures(&line.contents) {
+                    Some(captures) if line.should_format => captures,
+                    _ => {
+                        return_lines.push(line);
+                        continue;
+                    }
+                };
+                return_lines.push(OwnedLine {
+                    should_format: true,
+                    contents: captures[1].to_string() + &captures[2],
+                });
+                return_lines.push(OwnedLine {
+                    should_format: true,
+                    contents: captures[1].to_string() + &captures[3],
+                });
+            }
+        }
+    }
+
+    return_lines
+}
+
+/// How much further than the first line of a wrapped line its continuation lines
+/// are indented.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContinuationIndent {
+    /// Align continuation lines with the text after a list or definition marker
+    /// (see `LIST_MARKER`), and don't indent them any further otherwise.
+    Marker,
+    /// Align continuation lines like `Marker` does, and also with the text
+    /// after a leading `<li>`, `<dt>`, or `<dd>` tag.
+    Element,
+    /// Always indent continuation lines by this many extra spaces.
+    Fixed(usize),
+}
+
+impl std::str::FromStr for ContinuationIndent {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "marker" => return Ok(ContinuationIndent::Marker),
+            "element" => return Ok(ContinuationIndent::Element),
+            _ => {}
+        }
+        value.parse().map(ContinuationIndent::Fixed).map_err(|_| {
+            format!(
+                "expected 'marker', 'element', or a number of spaces, got '{}'",
+                value
+            )
+        })
+    }
+}
+
+/// A marker at the start of a line, which its continuation lines can be
+/// indented relative to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Marker {
+    /// A markdown numbered list item, like `1. ` or `1) `.
+    Ordered,
+    /// A markdown bulleted list item, like `* `, `- `, or `+ `.
+    Bullet,
+    /// A Bikeshed definition list term, `: `.
+    Term,
+    /// A Bikeshed definition list definition, `:: `.
+    Definition,
+    /// A `<li>` tag.
+    Li,
+    /// A `<dt>` tag.
+    Dt,
+    /// A `<dd>` tag.
+    Dd,
+}
+
+impl Marker {
+    // Whether this is markdown, rather than a tag.
+    fn is_markdown(self) -> bool {
+        matches!(
+            self,
+            Marker::Ordered | Marker::Bullet | Marker::Term | Marker::Definition
+        )
+    }
+}
+
+/// How far to indent the continuation lines of a line that starts with a
+/// particular marker, overriding `ContinuationIndent` for those lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContinuationRule {
+    pub marker: Marker,
+    /// The extra indentation, or `None` to align with the text after the
+    /// marker.
+    pub indent: Option<usize>,
+}
+
+impl std::str::FromStr for ContinuationRule {
+    type Err = String;
+
+    // Parses a rule like `li=align` or `::=4`, where the marker is one of
+    // `1.`, `*`, `:`, `::`, `li`, `dt`, or `dd`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            format!(
+                "expected MARKER=INDENT, where MARKER is '1.', '*', ':', '::', 'li', 'dt', or 'dd' and INDENT is 'align' or a number of spaces, got '{}'",
+                value
+            )
+        };
+        let (marker, indent) = value.split_once('=').ok_or_else(error)?;
+        let marker = match marker.trim() {
+            "1." => Marker::Ordered,
+            "*" => Marker::Bullet,
+            ":" => Marker::Term,
+            "::" => Marker::Definition,
+            "li" => Marker::Li,
+            "dt" => Marker::Dt,
+            "dd" => Marker::Dd,
+            _ => return Err(error()),
+        };
+        let indent = match indent.trim() {
+            "align" => None,
+            indent => Some(indent.parse().map_err(|_| error())?),
+        };
+        Ok(ContinuationRule { marker, indent })
+    }
+}
+
+lazy_static! {
+    // Matches a leading `<li>`, `<dt>`, or `<dd>` tag, along with the spaces
+    // that follow it.
+    static ref ITEM_TAG: Regex = Regex::new(r"^(?i)<(li|dt|dd)(\s[^<>]*)?>[ \t]*").unwrap();
+}
+
+// Returns the marker that `line` starts with, if any, along with the marker
+// itself and the spaces after it. Without `markdown`, only tags are markers.
+fn find_marker(line: &str, markdown: bool) -> Option<(Marker, &str)> {
+    if let Some(marker) = LIST_MARKER.find(line).filter(|_| markdown) {
+        let text = marker.as_str();
+        let kind = match text.trim_end() {
+            "::" => Marker::Definition,
+            ":" => Marker::Term,
+            bullet if bullet.starts_with(['*', '-', '+']) => Marker::Bullet,
+            _ => Marker::Ordered,
+        };
+        return Some((kind, text));
+    }
+    let tag = ITEM_TAG.captures(line)?;
+    let kind = match tag[1].to_ascii_lowercase().as_str() {
+        "li" => Marker::Li,
+        "dt" => Marker::Dt,
+        _ => Marker::Dd,
+    };
+    Some((kind, tag.get(0).unwrap().as_str()))
+}
+
+// Decides how far to indent the continuation lines of each wrapped line: by the
+// `rules` for the marker it starts with, if there are any, and otherwise by
+// `default`.
+struct ContinuationStrategy<'a> {
+    default: ContinuationIndent,
+    rules: &'a [ContinuationRule],
+    markdown: bool,
+    measure: Measure,
+}
+
+impl ContinuationStrategy<'_> {
+    // The extra indentation for the continuation lines of `line`, whose leading
+    // whitespace `indent` has been trimmed.
+    fn extra_indent(&self, indent: &str, line: &str) -> usize {
+        let marker = find_marker(line, self.markdown);
+        // Later rules override earlier ones for the same marker.
+        let rule =
+            marker.and_then(|(kind, _)| self.rules.iter().rev().find(|rule| rule.marker == kind));
+        let align = match (rule, self.default) {
+            (
+                Some(ContinuationRule {
+                    indent: Some(width),
+                    ..
+                }),
+                _,
+            ) => return *width,
+            (Some(_), _) => true,
+            (None, ContinuationIndent::Fixed(width)) => return width,
+            (None, ContinuationIndent::Marker) => {
+                marker.is_some_and(|(kind, _)| kind.is_markdown())
+            }
+            (None, ContinuationIndent::Element) => true,
+        };
+        match marker {
+            Some((_, text)) if align => {
+                self.measure.width(&(indent.to_owned() + text)) - self.measure.width(indent)
+            }
+            _ => 0,
+        }
+    }
+}
+
+// Returns the wrapped lines, along with the indices of those that are still
+// longer than `column_length`.
+fn wrap_lines(lines: Vec<OwnedLine>, options: &RewrapOptions) -> (Vec<String>, Vec<usize>) {
+    // A column length of 0 means there is no limit, so no line is too long.
+    let column_length = match options.column_length {
+        0 => usize::MAX,
+        column_length => column_length,
+    };
+    let measure = options.measure();
+    let mut rewrapped_lines: Vec<String> = Vec::new();
+    let mut overflowing_lines: Vec<usize> = Vec::new();
+    // The indentation width and continuation indentation of the Bikeshed
+    // definition (`:: `) we're in, if any. Content nested under a definition,
+    // like its later paragraphs, is never continued any shallower than the
+    // definition itself, even if it's indented less than the definition's text.
+    let mut definition: Option<(usize, String)> = None;
+    let mut switch_lists = SwitchLists::default();
+    for line in lines.iter() {
+        let in_switch = switch_lists.advance(&line.contents);
+        let (indent, rest) = split_indent(&line.contents);
+        if options.markdown
+            && LIST_MARKER
+                .find(rest)
+                .is_some_and(|marker| marker.as_str().trim_end() == "::")
+        {
+            definition = Some((measure.width(indent), continuation(indent, rest, options)));
+        } else if !rest.is_empty()
+            && definition
+                .as_ref()
+                .is_some_and(|(definition_indent, _)| measure.width(indent) <= *definition_indent)
+        {
+            definition = None;
+        }
+
+        if measure.width(&line.contents) <= column_length
+            || exempt_from_wrapping(&line.contents, in_switch)
+            || !line.should_format
+        {
+            rewrapped_lines.push(line.contents.to_string());
+        } else {
+            // The content of a `<dd>` in a switch list is aligned after its
+            // tag, whatever `continuation_indent` says.
+            let dd_hanging = find_marker(rest, false)
+                .filter(|(marker, _)| in_switch && *marker == Marker::Dd)
+                .map(|(_, tag)| indent.to_owned() + &" ".repeat(measure.width(tag)));
+            let hanging = dd_hanging
+                .as_deref()
+                .or(definition.as_ref().map(|(_, hanging)| hanging.as_str()))
+                .unwrap_or("");
+            for wrapped_line in wrap_single_line(&line.contents, options, hanging) {
+                if measure.width(&wrapped_line) > column_length {
+                    overflowing_lines.push(rewrapped_lines.len());
+                }
+                rewrapped_lines.push(wrapped_line);
+            }
+        }
+    }
+
+    (rewrapped_lines, overflowing_lines)
+}
+
+// A unit of text that `wrap_single_line()` will never break in the middle of.
+// Normally every space-separated word is its own `Word`, but in
+// attribute-wrapping mode the words of an open tag are grouped into a single
+// `Tag`, whose parts are the tag name followed by each of its attributes.
+enum WrapUnit<'a> {
+    Word(&'a str),
+    Tag(Vec<String>),
+}
+
+lazy_static! {
+    // Matches words ending in the beginning of an open tag, like `<input` or
+    // `<p><a`.
+    static ref UNCLOSED_OPEN_TAG: Regex = Regex::new(r#"<[a-zA-Z][^<>]*$"#).unwrap();
+}
+
+// Groups `words` into `WrapUnit`s. If `wrap_attributes` is false, every word is
+// its own unit.
+fn wrap_units<'a>(words: Vec<&'a str>, wrap_attributes: bool) -> Vec<WrapUnit<'a>> {
+    if !wrap_attributes {
+        return words.into_iter().map(WrapUnit::Word).collect();
+    }
+
+    let mut units = Vec::<WrapUnit>::new();
+    let mut words = words.into_iter();
+    while let Some(word) = words.next() {
+        if !UNCLOSED_OPEN_TAG.is_match(word) {
+            units.push(WrapUnit::Word(word));
+            continue;
+        }
+
+        // Collect the remaining words of the open tag, keeping quoted
+        // attribute values that contain spaces together as one part.
+        let mut parts = vec![word.to_string()];
+        let mut current_part = String::new();
+        let mut quote: Option<char> = None;
+        let mut closed = false;
+        for word in words.by_ref() {
+            if !current_part.is_empty() {
+                current_part.push(' ');
+            }
+            current_part.push_str(word);
+            for c in word.chars() {
+                match quote {
+                    Some(q) if c == q => quote = None,
+                    Some(_) => {}
+                    None if c == '"' || c == '\'' => quote = Some(c),
+                    None if c == '>' => closed = true,
+                    None => {}
+                }
+            }
+            if quote.is_none() {
+                parts.push(std::mem::take(&mut current_part));
+            }
+            if closed {
+                break;
+            }
+        }
+        if !current_part.is_empty() {
+            parts.push(current_part);
+        }
+        units.push(WrapUnit::Tag(parts));
+    }
+
+    units
+}
+
+lazy_static! {
+    // The indentation of a line, including the `>` markers of a blockquote
+    // line, which every continuation line repeats.
+    static ref INDENT: Regex = Regex::new(r"^\s*(>[ \t]*)*").unwrap();
+}
+
+// Splits `line` into its indentation and the rest of it.
+fn split_indent(line: &str) -> (&str, &str) {
+    line.split_at(INDENT.find(line).unwrap().end())
+}
+
+// The elements whose content the HTML Standard indents one level deeper than
+// their tags.
+const INDENTING_ELEMENTS: [&str; 23] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figure",
+    "footer",
+    "form",
+    "header",
+    "hgroup",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "search",
+    "section",
+    "ul",
+];
+
+// Whether an open tag for `name` closes the `open` element it's in, even
+// without an end tag, like a `<li>` closes the `<li>` before it.
+fn implicitly_closes(name: &str, open: &str) -> bool {
+    match name {
+        "li" => open == "li",
+        "dt" | "dd" => matches!(open, "dt" | "dd"),
+        _ => false,
+    }
+}
+
+lazy_static! {
+    // Matches the tags at the start of a line, like `</li><li>` or `<li><p>`.
+    static ref LEADING_TAGS: Regex = Regex::new(r"^(<[^<>]*>[ \t]*)*").unwrap();
+}
+
+// Reindents each of the `lines` being formatted with one space per
+// `INDENTING_ELEMENTS` element it's nested in. A line that starts with tags is
+// indented as deep as those tags leave it at their shallowest, so `</div>` is
+// indented like its `<div>`, and a `<li>` that closes the one before it is
+// indented like that one. Lines that start inside a tag or comment, and (with
+// `markdown`) markdown list items and blockquotes, keep their indentation.
+fn reindent(lines: &mut [OwnedLine], markdown: bool) {
+    let mut tokenizer = html::Tokenizer::default();
+    // The indenting elements we're in, outermost first.
+    let mut open: Vec<String> = Vec::new();
+    for line in lines {
+        let (indent, rest) = split_indent(&line.contents);
+        let in_text = tokenizer.in_text();
+        let leading = LEADING_TAGS.find(rest).unwrap().end();
+        let mut depth = open.len();
+        for (i, part) in [&rest[..leading], &rest[leading..]].into_iter().enumerate() {
+            for token in tokenizer.tokens(part) {
+                match token {
+                    html::Token::StartTag(name) if INDENTING_ELEMENTS.contains(&name.as_str()) => {
+                        while open
+                            .last()
+                            .is_some_and(|open| implicitly_closes(&name, open))
+                        {
+                            open.pop();
+                        }
+                        if i == 0 {
+                            depth = depth.min(open.len());
+                        }
+                        open.push(name);
+                    }
+                    html::Token::EndTag(name) => {
+                        if let Some(position) = open.iter().rposition(|open| *open == name) {
+                            open.truncate(position);
+                        }
+                    }
+                    _ => {}
+                }
+                if i == 0 {
+                    depth = depth.min(open.len());
+                }
+            }
+        }
+
+        let markup_indent = markdown && (indent.contains('>') || LIST_MARKER.is_match(rest));
+        if line.should_format && in_text && !rest.is_empty() && !markup_indent {
+            line.contents = " ".repeat(depth) + rest;
+        }
+    }
+}
+
+// Returns `line` with each tab in its indentation replaced by the spaces up to
+// the next tab stop.
+fn expand_indentation_tabs(line: &str, measure: Measure) -> String {
+    let (indent, rest) = split_indent(line);
+    let mut expanded = String::with_capacity(line.len());
+    for c in indent.chars() {
+        match c {
+            '\t' => {
+                let column = measure.width(&expanded);
+                expanded.push_str(&" ".repeat(measure.tab_width - column % measure.tab_width));
+            }
+            c => expanded.push(c),
+        }
+    }
+    expanded + rest
+}
+
+// The indentation of the continuation lines of a line with `indent`, whose
+// rest is `line`.
+fn continuation(indent: &str, line: &str, options: &RewrapOptions) -> String {
+    let strategy = ContinuationStrategy {
+        default: options.continuation_indent,
+        rules: &options.continuation_rules,
+        markdown: options.markdown,
+        measure: options.measure(),
+    };
+    indent.to_owned() + &" ".repeat(strategy.extra_indent(indent, line))
+}
+
+// Wraps `line`, indenting its continuation lines as `options` say, but at least
+// as far as `hanging`.
+fn wrap_single_line(line: &str, options: &RewrapOptions, hanging: &str) -> Vec<String> {
+    let mut return_lines = Vec::<String>::new();
+    let (indent, line) = split_indent(line);
+    let column_length = options.column_length;
+    let measure = options.measure();
+    let mut continuation = continuation(indent, line, options);
+    if measure.width(hanging) > measure.width(&continuation) {
+        continuation = hanging.to_string();
+    }
+
+    // The widest a unit can be and still fit on a continuation line.
+    let max_width = column_length.saturating_sub(measure.width(&continuation));
+    let words = join_elements(
+        line,
+        split_words(line),
+        &options.atomic_elements,
+        max_width,
+        measure,
+    );
+    let words = glue_to_previous_words(line, words);
+    let units = wrap_units(words, options.wrap_attributes);
+    if options.wrap_algorithm == WrapAlgorithm::Optimal {
+        if let Some(words) = units
+            .iter()
+            .map(|unit| match unit {
+                WrapUnit::Word(word) => Some(*word),
+                WrapUnit::Tag(_) => None,
+            })
+            .collect::<Option<Vec<&str>>>()
+            .filter(|words| !options.break_cjk || !words.iter().any(|word| cjk::has_break(word)))
+        {
+            return wrap_optimally(indent, &words, &continuation, column_length, measure);
+        }
+    }
+    let mut units = units.into_iter();
+    // This will never panic; even if `line` is empty after we trim it, the
+    // split collection will contain a single empty string. See
+    // https://play.rust-lang.org/?version=stable&mode=debug&edition=2021&gist=1035caa5a7a4324272c8966d36d323b4.
+    let mut current_line = String::from(indent);
+    match units.next().unwrap() {
+        WrapUnit::Word(word) => {
+            current_line.push_str(word);
+            if options.break_cjk {
+                current_line = break_cjk_word(
+                    current_line,
+                    indent.len(),
+                    &continuation,
+                    column_length,
+                    measure,
+                    &mut return_lines,
+                );
+            }
+        }
+        WrapUnit::Tag(parts) => {
+            current_line = wrap_tag(
+                parts,
+                current_line,
+                &continuation,
+                column_length,
+                measure,
+                &mut return_lines,
+            )
+        }
+    }
+    for unit in units {
+        let word = match unit {
+            WrapUnit::Word(word) => word.to_string(),
+            WrapUnit::Tag(parts) => {
+                let tag = parts.join(" ");
+                if measure.width(&continuation) + measure.width(&tag) <= column_length {
+                    tag
+                } else {
+                    current_line.push(' ');
+                    current_line = wrap_tag(
+                        parts,
+                        current_line,
+                        &continuation,
+                        column_length,
+                        measure,
+                        &mut return_lines,
+                    );
+                    continue;
+                }
+            }
+        };
+
+        if measure.width(&current_line) + 1 + measure.width(&word) <= column_length {
+            current_line.push_str(&(" ".to_owned() + &word));
+        } else if options.break_cjk
+            && !current_line.trim().is_empty()
+            && cjk::last_break(
+                &word,
+                column_length.saturating_sub(measure.width(&current_line) + 1),
+            )
+            .is_some()
+        {
+            // Fill the rest of the line with as much of the word as fits.
+            let start = current_line.len() + 1;
+            current_line.push_str(&(" ".to_owned() + &word));
+            current_line = break_cjk_word(
+                current_line,
+                start,
+                &continuation,
+                column_length,
+                measure,
+                &mut return_lines,
+            );
+        } else {
+            if !current_line.trim().is_empty() {
+                return_lines.push(current_line);
+            }
+            current_line = continuation.clone();
+            current_line.push_str(&word);
+            if options.break_cjk {
+                current_line = break_cjk_word(
+                    current_line,
+                    continuation.len(),
+                    &continuation,
+                    column_length,
+                    measure,
+                    &mut return_lines,
+                );
+            }
+        }
+    }
+
+    return_lines.push(current_line);
+    return_lines
+}
+
+// Breaks the word that starts at byte `start` of `current_line` (and ends it)
+// between CJK characters, wherever `cjk::last_break()` allows, until the rest of
+// it fits. Full lines are appended to `return_lines`, and the rest of the word
+// continues on lines starting with `continuation`. Returns the (unfinished)
+// line that the word ends on.
+fn break_cjk_word(
+    mut current_line: String,
+    mut start: usize,
+    continuation: &str,
+    column_length: usize,
+    measure: Measure,
+    return_lines: &mut Vec<String>,
+) -> String {
+    while measure.width(&current_line) > column_length {
+        let available = column_length.saturating_sub(measure.width(&current_line[..start]));
+        let Some(i) = cjk::last_break(&current_line[start..], available) else {
+            break;
+        };
+        let rest = current_line.split_off(start + i);
+        return_lines.push(current_line);
+        current_line = continuation.to_owned() + &rest;
+        start = continuation.len();
+    }
+    current_line
+}
+
+// Wraps `words` with `optimal::breaks()`, after `indent` on the first line and
+// `continuation` on the rest. Only lines of plain words are wrapped this way;
+// `wrap_single_line()` wraps lines with `Tag`s greedily.
+fn wrap_optimally(
+    indent: &str,
+    words: &[&str],
+    continuation: &str,
+    column_length: usize,
+    measure: Measure,
+) -> Vec<String> {
+    let widths: Vec<usize> = words.iter().map(|word| measure.width(word)).collect();
+    let breaks = optimal::breaks(
+        &widths,
+        column_length.saturating_sub(measure.width(indent)),
+        column_length.saturating_sub(measure.width(continuation)),
+    );
+    let mut starts = vec![0];
+    starts.extend(&breaks);
+    let mut ends = breaks;
+    ends.push(words.len());
+    starts
+        .into_iter()
+        .zip(ends)
+        .enumerate()
+        .map(|(i, (start, end))| {
+            let prefix = if i == 0 { indent } else { continuation };
+            prefix.to_owned() + &words[start..end].join(" ")
+        })
+        .collect()
+}
+
+// The delimiters of the spans that `split_words()` never splits: comments, and
+// Bikeshed's autolink shorthands (like `{{Window/open()}}` or `[=in parallel=]`)
+// and value shorthands (like `''display: none''`), which Bikeshed wouldn't
+// recognize if they spanned lines.
+const ATOMIC_SPANS: [(&str, &str); 10] = [
+    ("<!--", "-->"),
+    ("{{", "}}"),
+    ("[=", "=]"),
+    ("[[", "]]"),
+    ("<{", "}>"),
+    ("<<", ">>"),
+    ("[$", "$]"),
+    ("[:", ":]"),
+    ("[^", "^]"),
+    ("''", "''"),
+];
+
+// Splits `line` into space-separated words, except that each of the
+// `ATOMIC_SPANS` in it, like an inline comment (`<!-- a reviewer note -->`), is
+// part of a single word, so that wrapping never breaks it up. So is each
+// attribute in a tag along with its value, even when the value is quoted and
+// has spaces (like `title="a b"`), or there are spaces around its `=` (like
+// `href = "https://example.com/"`), so that a value like a URL is never
+// separated from its attribute or broken. A span opener or quote that is never
+// closed on the line is just text.
+fn split_words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut word_start = 0;
+    // The end of the span or attribute value we're in, if any.
+    let mut span_end: Option<usize> = None;
+    let mut in_tag = false;
+    for (i, c) in line.char_indices() {
+        if span_end.is_some_and(|end| i < end) {
+            continue;
+        }
+        span_end = if in_tag {
+            ((c == '"' || c == '\'') && line[..i].trim_end().ends_with('='))
+                .then(|| line[i + 1..].find(c).map(|end| i + 1 + end + 1))
+                .flatten()
+        } else {
+            ATOMIC_SPANS.iter().find_map(|(open, close)| {
+                let after_open = i + open.len();
+                line[i..].starts_with(open).then_some(())?;
+                line[after_open..]
+                    .find(close)
+                    .map(|end| after_open + end + close.len())
+            })
+        };
+        if span_end.is_some() {
+            continue;
+        }
+        match c {
+            '<' if line[i + 1..].starts_with(|c: char| c.is_ascii_alphabetic()) => in_tag = true,
+            '>' => in_tag = false,
+            ' ' if in_tag
+                && (line[..i].trim_end().ends_with('=')
+                    || line[i..].trim_start().starts_with('=')) => {}
+            ' ' => {
+                words.push(&line[word_start..i]);
+                word_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    words.push(&line[word_start..]);
+    words
+}
+
+// Joins the `words` of `line` that make up each inline element of one of `tags`,
+// from the word with its open tag through the word with its end tag, into a
+// single word, as long as it is at most `max_width` wide.
+fn join_elements<'a>(
+    line: &'a str,
+    words: Vec<&'a str>,
+    tags: &[String],
+    max_width: usize,
+    measure: Measure,
+) -> Vec<&'a str> {
+    if tags.is_empty() {
+        return words;
+    }
+
+    // The words are separated by single spaces, so each element is a slice of
+    // `line`.
+    let mut starts = Vec::with_capacity(words.len());
+    let mut offset = 0;
+    for word in &words {
+        starts.push(offset);
+        offset += word.len() + 1;
+    }
+    let mut joined = Vec::with_capacity(words.len());
+    let mut i = 0;
+    while i < words.len() {
+        let last = open_tag(words[i], tags).and_then(|(position, tag)| {
+            let open = starts[i] + position;
+            let end = open + line[open..].find(&format!("</{}>", tag))? + tag.len() + 3;
+            let last = starts.iter().rposition(|&start| start < end).unwrap();
+            let element = &line[starts[i]..starts[last] + words[last].len()];
+            (last > i && measure.width(element) <= max_width).then_some(last)
+        });
+        match last {
+            Some(last) => {
+                joined.push(&line[starts[i]..starts[last] + words[last].len()]);
+                i = last + 1;
+            }
+            None => {
+                joined.push(words[i]);
+                i += 1;
+            }
+        }
+    }
+    joined
+}
+
+lazy_static! {
+    // Matches a word that is a Bikeshed bibliography reference, like
+    // `[[RFC7231]]` or `[[!FETCH]]`, possibly with punctuation around it.
+    static ref CITATION: Regex = Regex::new(r"^[^\w\s\[]*\[\[!?[^\]\s]+\]\]").unwrap();
+    // Matches a word of nothing but closing punctuation and end tags, like `);`
+    // or `.</p>`. Opening punctuation (like `(` or `“`) and dashes begin the
+    // text after them instead, so they may start a line.
+    static ref PUNCTUATION: Regex =
+        Regex::new(r"^(</[a-zA-Z][\w-]*>|[.,;:!?)\]}”’…])+$").unwrap();
+}
+
+// Whether `word` must stay on the same line as the word before it. By
+// editorial convention, a citation never starts a line, and neither does
+// closing punctuation, which ends the text before it.
+fn sticks_to_previous_word(word: &str) -> bool {
+    CITATION.is_match(word) || PUNCTUATION.is_match(word)
+}
+
+// Joins each of the `words` of `line` that sticks to the word before it onto
+// that word.
+fn glue_to_previous_words<'a>(line: &'a str, words: Vec<&'a str>) -> Vec<&'a str> {
+    let mut glued: Vec<&str> = Vec::with_capacity(words.len());
+    // The words are separated by single spaces, so each glued word is a slice
+    // of `line`, ending where the word glued onto it does.
+    let mut offset = 0;
+    for word in words {
+        match glued.last_mut() {
+            Some(previous) if !previous.is_empty() && sticks_to_previous_word(word) => {
+                let start = offset - 1 - previous.len();
+                *previous = &line[start..offset + word.len()];
+            }
+            _ => glued.push(word),
+        }
+        offset += word.len() + 1;
+    }
+    glued
+}
+
+// The position and tag of the first open tag for one of `tags` in `word`.
+fn open_tag<'a>(word: &str, tags: &'a [String]) -> Option<(usize, &'a String)> {
+    word.match_indices('<').find_map(|(position, _)| {
+        let after = &word[position + 1..];
+        tags.iter().find_map(|tag| {
+            let rest = after.strip_prefix(tag.as_str())?;
+            matches!(rest.chars().next(), None | Some('>' | '/')).then_some((position, tag))
+        })
+    })
+}
+
+// Appends an open tag that does not fit within `column_length` on a line of its
+// own to `current_line`, breaking between its attributes. Continuation lines
+// are aligned under the tag's first attribute, repeating the indentation (like
+// the `>` of a blockquote) of the line the tag starts on, unless an attribute
+// would not fit there, in which case they are indented two spaces past
+// `indent`. Returns the (unfinished) line that the end of the tag was placed
+// on.
+fn wrap_tag(
+    parts: Vec<String>,
+    mut current_line: String,
+    indent: &str,
+    column_length: usize,
+    measure: Measure,
+    return_lines: &mut Vec<String>,
+) -> String {
+    let mut parts = parts.into_iter();
+    let name = parts.next().unwrap();
+    let first_attribute = parts.next().unwrap_or_default();
+
+    // Start the tag on a new line if not even its name and first attribute fit
+    // on the current one.
+    if !current_line.trim().is_empty()
+        && measure.width(&current_line) + measure.width(&name) + 1 + measure.width(&first_attribute)
+            > column_length
+    {
+        return_lines.push(current_line.trim_end().to_string());
+        current_line = String::from(indent);
+    }
+    current_line.push_str(&name);
+    let (prefix, _rest) = split_indent(&current_line);
+    let mut alignment =
+        prefix.to_owned() + &" ".repeat(measure.width(&current_line) + 1 - measure.width(prefix));
+    if !first_attribute.is_empty() {
+        current_line.push_str(&(" ".to_owned() + &first_attribute));
+    }
+
+    let parts: Vec<String> = parts.collect();
+    let widest = parts.iter().map(|part| measure.width(part)).max();
+    if widest.is_some_and(|widest| measure.width(&alignment) + widest > column_length) {
+        alignment = indent.to_owned() + "  ";
+    }
+    for part in parts {
+        if measure.width(&current_line) + 1 + measure.width(&part) <= column_length {
+            current_line.push_str(&(" ".to_owned() + &part));
+        } else {
+            return_lines.push(current_line);
+            current_line = alignment.clone() + &part;
+        }
+    }
+
+    current_line
+}
@@ -0,0 +1,117 @@
+//! Summarizes which sections of a spec formatting touched, so that PR authors
+//! can mention them and reviewers can jump straight to them. A section is
+//! everything between a heading and the next one.
+
+use super::diff::Hunk;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref HTML_HEADING: Regex = Regex::new(r#"<h([1-6])([^>]*)>(.*)</h[1-6]>"#).unwrap();
+    static ref MARKDOWN_HEADING: Regex =
+        Regex::new(r#"^(#{1,6})\s+(.*?)\s*#*\s*(?:\{#([^}\s]+)\})?\s*$"#).unwrap();
+    static ref ID_ATTRIBUTE: Regex =
+        Regex::new(r#"\sid\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap();
+    static ref TAG: Regex = Regex::new(r#"<[^>]*>"#).unwrap();
+}
+
+const PREAMBLE: &str = "(before the first heading)";
+
+struct Heading {
+    // 1 for `<h1>` or `#`, through 6.
+    level: usize,
+    text: String,
+    id: Option<String>,
+}
+
+// Returns the heading on `line`, if any. Its id comes from the `id` attribute
+// of an HTML heading, or the `{#id}` after a Bikeshed markdown heading.
+fn heading(line: &str) -> Option<Heading> {
+    let (level, text, id) = match HTML_HEADING.captures(line) {
+        Some(captures) => (
+            captures[1].parse().unwrap(),
+            captures.get(3).unwrap().as_str(),
+            ID_ATTRIBUTE.captures(&captures[2]).and_then(|id| {
+                (1..=3)
+                    .find_map(|i| id.get(i))
+                    .map(|id| id.as_str().to_string())
+            }),
+        ),
+        None => {
+            let captures = MARKDOWN_HEADING.captures(line)?;
+            (
+                captures[1].len(),
+                captures.get(2).unwrap().as_str(),
+                captures.get(3).map(|id| id.as_str().to_string()),
+            )
+        }
+    };
+    Some(Heading {
+        level,
+        text: TAG.replace_all(text, "").trim().to_string(),
+        id,
+    })
+}
+
+/// Returns the headings of the sections of `spec` that `hunks` change, in order
+/// and without duplicates. `hunks` must describe changes to `spec`.
+pub fn changed_sections(spec: &str, hunks: &[Hunk]) -> Vec<String> {
+    // `line_sections[i]` is the heading of the section that (0-based) line `i`
+    // belongs to.
+    let mut line_sections = Vec::<String>::new();
+    let mut current_section = String::from(PREAMBLE);
+    for line in spec.split('\n') {
+        if let Some(heading) = heading(line) {
+            current_section = heading.text;
+        }
+        line_sections.push(current_section.clone());
+    }
+
+    let mut sections = Vec::<String>::new();
+    for hunk in hunks {
+        // A single hunk can contain several changes, separated by context.
+        let mut line = hunk.old_start - 1;
+        for hunk_line in &hunk.lines {
+            let changed_line = match hunk_line.chars().next() {
+                Some(' ') => {
+                    line += 1;
+                    continue;
+                }
+                Some('-') => {
+                    line += 1;
+                    line - 1
+                }
+                // Inserted lines belong to the section of the line before them.
+                Some('+') => line.saturating_sub(1),
+                // The "\ No newline at end of file" marker.
+                _ => continue,
+            };
+
+            if let Some(section) = line_sections.get(changed_line) {
+                if !sections.contains(section) {
+                    sections.push(section.clone());
+                }
+            }
+        }
+    }
+
+    sections
+}
+
+/// Returns the 0-based, inclusive range of lines of `spec` in the section whose
+/// heading has the text or id `section`: from its heading up to the next
+/// heading of the same or a higher level (like the next `<h2>` after an
+/// `<h3>`), or the end of the spec. Returns `None` if there is no such section.
+pub fn section_lines(spec: &str, section: &str) -> Option<(usize, usize)> {
+    let lines: Vec<&str> = spec.split('\n').collect();
+    let (start, level) = lines.iter().enumerate().find_map(|(i, line)| {
+        let heading = heading(line)?;
+        (heading.text == section || heading.id.as_deref() == Some(section))
+            .then_some((i, heading.level))
+    })?;
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| heading(line).is_some_and(|heading| heading.level <= level))
+        .map_or(lines.len() - 1, |i| start + i);
+    Some((start, end))
+}
@@ -0,0 +1,243 @@
+use specfmt_core::json;
+use specfmt_core::rewrapper::ContinuationIndent;
+use specfmt_core::rewrapper::ContinuationRule;
+use specfmt_core::rewrapper::ParagraphContent;
+use specfmt_core::rewrapper::RewrapOptions;
+use specfmt_core::rewrapper::WrapAlgorithm;
+use specfmt_core::rewrapper::MIN_COLUMN_LENGTH;
+use wasm_bindgen::prelude::*;
+
+// JavaScript bindings for the formatter, so that it can run in browsers (like
+// the original web version of the rewrapper, https://github.com/domenic/rewrapper)
+// and in Node-based spec tooling:
+//
+//   import { format } from "specfmt-wasm";
+//   const formatted = format(source, { wrap: 80, formatJson: true });
+//
+// Build with `wasm-pack build specfmt-wasm`.
+
+#[wasm_bindgen(typescript_custom_section)]
+const FORMAT_OPTIONS: &str = r#"
+export interface FormatOptions {
+  wrap?: number;
+  wrapAttributes?: boolean;
+  preserveBreaksBefore?: string[];
+  exemptTags?: string[];
+  atomicElements?: string[];
+  paragraphContent?: "same-line" | "next-line";
+  continuationIndent?: "marker" | "element" | number;
+  continuationRules?: string[];
+  wrapAlgorithm?: "greedy" | "optimal";
+  respectSentenceBreaks?: boolean;
+  noUnwrap?: boolean;
+  noMarkdown?: boolean;
+  breakCjk?: boolean;
+  renderedEntityWidth?: boolean;
+  tabWidth?: number;
+  expandTabs?: boolean;
+  stripTrailingWhitespace?: boolean;
+  reindent?: boolean;
+  formatJson?: boolean;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    // A plain JavaScript object, every property of which is optional.
+    #[wasm_bindgen(typescript_type = "FormatOptions")]
+    pub type FormatOptions;
+
+    #[wasm_bindgen(method, getter)]
+    fn wrap(this: &FormatOptions) -> Option<usize>;
+    #[wasm_bindgen(method, getter = wrapAttributes)]
+    fn wrap_attributes(this: &FormatOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter = preserveBreaksBefore)]
+    fn preserve_breaks_before(this: &FormatOptions) -> Option<Box<[JsValue]>>;
+    #[wasm_bindgen(method, getter = exemptTags)]
+    fn exempt_tags(this: &FormatOptions) -> Option<Box<[JsValue]>>;
+    #[wasm_bindgen(method, getter = atomicElements)]
+    fn atomic_elements(this: &FormatOptions) -> Option<Box<[JsValue]>>;
+    #[wasm_bindgen(method, getter = paragraphContent)]
+    fn paragraph_content(this: &FormatOptions) -> Option<String>;
+    #[wasm_bindgen(method, getter = continuationIndent)]
+    fn continuation_indent(this: &FormatOptions) -> JsValue;
+    #[wasm_bindgen(method, getter = continuationRules)]
+    fn continuation_rules(this: &FormatOptions) -> Option<Box<[JsValue]>>;
+    #[wasm_bindgen(method, getter = wrapAlgorithm)]
+    fn wrap_algorithm(this: &FormatOptions) -> Option<String>;
+    #[wasm_bindgen(method, getter = respectSentenceBreaks)]
+    fn respect_sentence_breaks(this: &FormatOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter = noUnwrap)]
+    fn no_unwrap(this: &FormatOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter = noMarkdown)]
+    fn no_markdown(this: &FormatOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter = breakCjk)]
+    fn break_cjk(this: &FormatOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter = renderedEntityWidth)]
+    fn rendered_entity_width(this: &FormatOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter = tabWidth)]
+    fn tab_width(this: &FormatOptions) -> Option<usize>;
+    #[wasm_bindgen(method, getter = expandTabs)]
+    fn expand_tabs(this: &FormatOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter = stripTrailingWhitespace)]
+    fn strip_trailing_whitespace(this: &FormatOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter)]
+    fn reindent(this: &FormatOptions) -> Option<bool>;
+    #[wasm_bindgen(method, getter = formatJson)]
+    fn format_json(this: &FormatOptions) -> Option<bool>;
+}
+
+// Converts the array of the `name` option to strings.
+fn strings(values: &[JsValue], name: &str) -> Result<Vec<String>, JsError> {
+    values
+        .iter()
+        .map(|value| {
+            value
+                .as_string()
+                .ok_or_else(|| JsError::new(&format!("{} must be an array of strings", name)))
+        })
+        .collect()
+}
+
+fn rewrap_options(options: &FormatOptions) -> Result<RewrapOptions, JsError> {
+    let mut rewrap_options = RewrapOptions::default();
+    if let Some(wrap) = options.wrap() {
+        if wrap != 0 && wrap < MIN_COLUMN_LENGTH {
+            return Err(JsError::new(&format!(
+                "wrap must be 0 or at least {}, not {}",
+                MIN_COLUMN_LENGTH, wrap
+            )));
+        }
+        rewrap_options = rewrap_options.column_length(wrap);
+    }
+    if let Some(wrap_attributes) = options.wrap_attributes() {
+        rewrap_options = rewrap_options.wrap_attributes(wrap_attributes);
+    }
+    if let Some(tags) = options.preserve_breaks_before() {
+        rewrap_options =
+            rewrap_options.preserve_breaks_before(strings(&tags, "preserveBreaksBefore")?);
+    }
+    if let Some(tags) = options.exempt_tags() {
+        rewrap_options = rewrap_options.exempt_tags(strings(&tags, "exemptTags")?);
+    }
+    if let Some(tags) = options.atomic_elements() {
+        rewrap_options = rewrap_options.atomic_elements(strings(&tags, "atomicElements")?);
+    }
+    match options.paragraph_content().as_deref() {
+        None => {}
+        Some("same-line") => {
+            rewrap_options = rewrap_options.paragraph_content(ParagraphContent::SameLine)
+        }
+        Some("next-line") => {
+            rewrap_options = rewrap_options.paragraph_content(ParagraphContent::NextLine)
+        }
+        Some(other) => {
+            return Err(JsError::new(&format!(
+                "paragraphContent must be \"same-line\" or \"next-line\", not \"{}\"",
+                other
+            )))
+        }
+    }
+    let continuation_indent = options.continuation_indent();
+    if let Some(width) = continuation_indent.as_f64() {
+        if width < 0.0 || width.fract() != 0.0 {
+            return Err(JsError::new(&format!(
+                "continuationIndent must be a whole number of spaces, not {}",
+                width
+            )));
+        }
+        let width = ContinuationIndent::Fixed(width as usize);
+        rewrap_options = rewrap_options.continuation_indent(width);
+    } else if let Some(indent) = continuation_indent.as_string() {
+        let indent = indent
+            .parse()
+            .map_err(|error: String| JsError::new(&error))?;
+        rewrap_options = rewrap_options.continuation_indent(indent);
+    } else if !continuation_indent.is_undefined() {
+        return Err(JsError::new(
+            "continuationIndent must be \"marker\", \"element\", or a number of spaces",
+        ));
+    }
+    if let Some(rules) = options.continuation_rules() {
+        let rules = strings(&rules, "continuationRules")?
+            .iter()
+            .map(|rule| rule.parse().map_err(|error: String| JsError::new(&error)))
+            .collect::<Result<Vec<ContinuationRule>, JsError>>()?;
+        rewrap_options = rewrap_options.continuation_rules(rules);
+    }
+    match options.wrap_algorithm().as_deref() {
+        None => {}
+        Some("greedy") => rewrap_options = rewrap_options.wrap_algorithm(WrapAlgorithm::Greedy),
+        Some("optimal") => rewrap_options = rewrap_options.wrap_algorithm(WrapAlgorithm::Optimal),
+        Some(other) => {
+            return Err(JsError::new(&format!(
+                "wrapAlgorithm must be \"greedy\" or \"optimal\", not \"{}\"",
+                other
+            )))
+        }
+    }
+    if let Some(respect_sentence_breaks) = options.respect_sentence_breaks() {
+        rewrap_options = rewrap_options.respect_sentence_breaks(respect_sentence_breaks);
+    }
+    if let Some(no_unwrap) = options.no_unwrap() {
+        rewrap_options = rewrap_options.unwrap_paragraphs(!no_unwrap);
+    }
+    if let Some(no_markdown) = options.no_markdown() {
+        rewrap_options = rewrap_options.markdown(!no_markdown);
+    }
+    if let Some(break_cjk) = options.break_cjk() {
+        rewrap_options = rewrap_options.break_cjk(break_cjk);
+    }
+    if let Some(rendered_entity_width) = options.rendered_entity_width() {
+        rewrap_options = rewrap_options.rendered_entity_width(rendered_entity_width);
+    }
+    if let Some(tab_width) = options.tab_width() {
+        if tab_width == 0 {
+            return Err(JsError::new("tabWidth must be at least 1"));
+        }
+        rewrap_options = rewrap_options.tab_width(tab_width);
+    }
+    if let Some(expand_tabs) = options.expand_tabs() {
+        rewrap_options = rewrap_options.expand_tabs(expand_tabs);
+    }
+    if let Some(strip_trailing_whitespace) = options.strip_trailing_whitespace() {
+        rewrap_options = rewrap_options.strip_trailing_whitespace(strip_trailing_whitespace);
+    }
+    if let Some(reindent) = options.reindent() {
+        rewrap_options = rewrap_options.reindent(reindent);
+    }
+    Ok(rewrap_options)
+}
+
+// Formats all of `source`, returning the formatted spec.
+#[wasm_bindgen]
+pub fn format(source: &str, options: Option<FormatOptions>) -> Result<String, JsError> {
+    let (rewrap_options, format_json) = match &options {
+        Some(options) => (rewrap_options(options)?, options.format_json()),
+        None => (RewrapOptions::default(), None),
+    };
+    let source = if format_json == Some(true) {
+        json::format_json_blocks(source)
+    } else {
+        source.to_string()
+    };
+    Ok(specfmt_core::format(&source, &rewrap_options)
+        .lines
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Options are JavaScript objects, which only exist in WebAssembly, but
+    // formatting with the default options runs natively too.
+    #[test]
+    fn format_with_default_options() {
+        let formatted = format("<p>One\ntwo</p>\n\n<p>Three</p>\n", None).ok();
+        assert_eq!(
+            formatted.as_deref(),
+            Some("<p>One two</p>\n\n<p>Three</p>\n")
+        );
+    }
+}
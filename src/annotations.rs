@@ -0,0 +1,41 @@
+// Helpers for reporting problems with a spec in formats that CI systems
+// understand.
+
+// Escapes `data` for use in a GitHub Actions workflow command. Property values
+// (like `file`) additionally need `:` and `,` escaped. See
+// https://github.com/actions/toolkit/blob/main/packages/core/src/command.ts.
+fn escape_github_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+fn escape_github_property(property: &str) -> String {
+    escape_github_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+// Renders a GitHub Actions workflow command that annotates `lines` (1-based,
+// inclusive) of `file`, or the whole pull request if there is no `file`.
+// `level` is one of "error", "warning", or "notice".
+pub fn github_annotation(
+    level: &str,
+    file: Option<&str>,
+    lines: Option<(usize, usize)>,
+    message: &str,
+) -> String {
+    let mut properties = Vec::new();
+    if let Some(file) = file {
+        properties.push(format!("file={}", escape_github_property(file)));
+        if let Some((start, end)) = lines {
+            properties.push(format!("line={},endLine={}", start, end));
+        }
+    }
+    properties.push(String::from("title=specfmt"));
+    format!(
+        "::{} {}::{}",
+        level,
+        properties.join(","),
+        escape_github_data(message)
+    )
+}
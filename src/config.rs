@@ -0,0 +1,302 @@
+use super::error::SpecfmtError;
+use super::FormatOptions;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use clap::CommandFactory;
+use clap::FromArgMatches;
+use std::path::Path;
+use std::path::PathBuf;
+
+// Spec repositories pin their formatting conventions in a `.specfmt.toml` file,
+// found by walking up from the spec, so contributors don't need to remember
+// flags. For example:
+//
+//   wrap = 80
+//   base-branch = ["main", "upstream/main"]
+//   preserve-breaks-before = ["dfn"]
+//
+// Keys are the names of command line flags, and flags given on the command line
+// override them. Settings may also live in a `[specfmt]` table, and all other
+// tables are ignored, so the file can be shared with other tools.
+//
+// We only parse the subset of TOML that these settings need: strings, integers,
+// booleans, and single-line arrays of those.
+
+pub const FILENAME: &str = ".specfmt.toml";
+
+// The flags that can be configured. The others only make sense for a single
+// invocation (like `--range`), or for a particular tool (like
+// `--error-format`).
+const KEYS: [&str; 27] = [
+    "wrap",
+    "full-spec",
+    "base-branch",
+    "on-base-branch",
+    "word-diff",
+    "scope",
+    "format-json",
+    "wrap-attributes",
+    "preserve-breaks-before",
+    "exempt-tags",
+    "atomic-elements",
+    "paragraph-content",
+    "allow-long-line",
+    "continuation-indent",
+    "wrap-algorithm",
+    "respect-sentence-breaks",
+    "no-unwrap",
+    "no-markdown",
+    "break-cjk",
+    "rendered-entity-width",
+    "tab-width",
+    "expand-tabs",
+    "line-ending",
+    "ensure-final-newline",
+    "strip-trailing-whitespace",
+    "reindent",
+    "continuation-rules",
+];
+
+// Parses configuration values as if they were passed on the command line, so
+// they are validated exactly like flags are.
+#[derive(clap::Parser)]
+#[command(no_binary_name = true)]
+struct ConfigArgs {
+    #[command(flatten)]
+    format: FormatOptions,
+}
+
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+// Finds the configuration file that applies to `spec`, which is the nearest one
+// in its directory or any of its ancestors.
+pub fn find(spec: &Path) -> Option<PathBuf> {
+    let directory = spec
+        .parent()
+        .filter(|directory| !directory.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let directory = directory.canonicalize().ok()?;
+    directory
+        .ancestors()
+        .map(|directory| directory.join(FILENAME))
+        .find(|config| config.is_file())
+}
+
+// Returns the value at the start of `text`, and the rest of `text`.
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    let text = text.trim_start();
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(value), &rest[i + 1..])),
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, c @ ('"' | '\\'))) => value.push(c),
+                    _ => return Err(String::from("unsupported escape sequence")),
+                },
+                c => value.push(c),
+            }
+        }
+        return Err(String::from("unterminated string"));
+    }
+    if let Some(rest) = text.strip_prefix('\'') {
+        return match rest.split_once('\'') {
+            Some((value, rest)) => Ok((Value::String(value.to_string()), rest)),
+            None => Err(String::from("unterminated string")),
+        };
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(rest) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), rest));
+            }
+            let (value, after) = parse_value(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err(String::from("expected ',' or ']' in array"));
+            }
+        }
+    }
+
+    let end = text
+        .find(|c: char| c == ',' || c == ']' || c.is_whitespace())
+        .unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    match word {
+        "true" => Ok((Value::Boolean(true), rest)),
+        "false" => Ok((Value::Boolean(false), rest)),
+        _ => match word.replace('_', "").parse() {
+            Ok(integer) => Ok((Value::Integer(integer), rest)),
+            Err(_) => Err(format!("unsupported value '{}'", word)),
+        },
+    }
+}
+
+// Removes a trailing comment from `line`, minding `#`s in strings.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+// Parses the configuration file `contents` into the command line flags it
+// stands for.
+pub fn parse(contents: &str) -> Result<Vec<String>, String> {
+    let mut flags = Vec::new();
+    let mut in_specfmt_table = true;
+    for (i, line) in contents.lines().enumerate() {
+        let error = |message: String| format!("line {}: {}", i + 1, message);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(table) = line.strip_prefix('[') {
+            let table = table
+                .strip_suffix(']')
+                .ok_or_else(|| error(String::from("expected ']'")))?;
+            in_specfmt_table = table.trim() == "specfmt";
+            continue;
+        }
+        if !in_specfmt_table {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(String::from("expected 'key = value'")))?;
+        let key = key.trim();
+        if !KEYS.contains(&key) {
+            return Err(error(format!("unknown setting '{}'", key)));
+        }
+        let (value, rest) = parse_value(value).map_err(error)?;
+        if !rest.trim().is_empty() {
+            return Err(error(format!("unexpected '{}'", rest.trim())));
+        }
+
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Boolean(true) => flags.push(format!("--{}", key)),
+                Value::Boolean(false) => {}
+                Value::Integer(integer) => flags.push(format!("--{}={}", key, integer)),
+                Value::String(string) => flags.push(format!("--{}={}", key, string)),
+                Value::Array(_) => {
+                    return Err(error(String::from("nested arrays are unsupported")))
+                }
+            }
+        }
+    }
+    Ok(flags)
+}
+
+// Parses `flags` (without a binary name) into the options they stand for, along
+// with the matches they were parsed from.
+pub fn parse_flags(flags: &[String]) -> Result<(FormatOptions, ArgMatches), String> {
+    let matches = ConfigArgs::command()
+        .try_get_matches_from(flags)
+        .map_err(|error| super::diagnostics::clap_error_message(&error))?;
+    let options = ConfigArgs::from_arg_matches(&matches)
+        .map_err(|error| super::diagnostics::clap_error_message(&error))?
+        .format;
+    Ok((options, matches))
+}
+
+// Returns `options` (parsed from `command_line`), with the settings from the
+// configuration file that applies to `spec` filling in for the flags that
+// weren't passed on the command line.
+pub fn apply(
+    options: &FormatOptions,
+    command_line: &ArgMatches,
+    spec: &Path,
+) -> Result<FormatOptions, SpecfmtError> {
+    let mut options = options.clone();
+    let config = match find(spec) {
+        Some(config) => config,
+        None => return Ok(options),
+    };
+
+    let invalid = |message: String| SpecfmtError::Config {
+        file: config.clone(),
+        message,
+    };
+    let contents = std::fs::read_to_string(&config).map_err(|error| invalid(error.to_string()))?;
+    let flags = parse(&contents).map_err(invalid)?;
+    let (configured, matches) = parse_flags(&flags).map_err(invalid)?;
+
+    let applies = |id: &str| {
+        matches.value_source(id) == Some(ValueSource::CommandLine)
+            && command_line.value_source(id) != Some(ValueSource::CommandLine)
+    };
+    macro_rules! merge {
+        ($($field:ident),*) => {
+            $(
+                if applies(stringify!($field)) {
+                    options.$field = configured.$field;
+                }
+            )*
+        };
+    }
+    merge!(
+        wrap,
+        base_branch,
+        on_base_branch,
+        word_diff,
+        scope,
+        format_json,
+        wrap_attributes,
+        preserve_breaks_before,
+        exempt_tags,
+        atomic_elements,
+        paragraph_content,
+        allow_long_line,
+        continuation_indent,
+        wrap_algorithm,
+        respect_sentence_breaks,
+        no_unwrap,
+        no_markdown,
+        break_cjk,
+        rendered_entity_width,
+        tab_width,
+        expand_tabs,
+        line_ending,
+        ensure_final_newline,
+        strip_trailing_whitespace,
+        reindent,
+        continuation_rules
+    );
+    // Formatting the entire spec conflicts with scoping formatting to staged
+    // changes or a range, which the command line takes precedence for.
+    if !options.staged && !options.has_range() {
+        merge!(full_spec);
+    }
+    Ok(options)
+}
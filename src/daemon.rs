@@ -0,0 +1,207 @@
+use super::config;
+use super::jsonrpc;
+use super::jsonrpc::Value;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+// A long-lived process that formats specs on request, for editors and bots
+// that format many hunks in quick succession and would otherwise pay for
+// process startup each time. Clients connect to a local port and send JSON-RPC
+// requests, framed like Language Server Protocol messages:
+//
+//   {"jsonrpc": "2.0", "id": 1, "method": "format", "params": {
+//     "path": "spec.bs", "text": "...", "options": {"wrap": 80}}}
+//
+// which formats `text` (or the file at `path`, if there is no `text`) and
+// responds with:
+//
+//   {"jsonrpc": "2.0", "id": 1, "result": {
+//     "text": "...", "changed": true, "warnings": [{"code": "overflow", ...}]}}
+//
+// `options` are named like command line flags, as in `.specfmt.toml`, and the
+// configuration file that applies to `path` fills in for the missing ones.
+// Formatting is scoped like `--stdin-filepath` scopes it: to the changes of the
+// file at `path`, unless formatting the entire spec or a range. The daemon
+// never writes to specs itself; clients apply the formatted `text`.
+//
+// Each connection is served on its own thread, and may send any number of
+// requests. A `shutdown` request stops the daemon.
+
+// Turns request `options` into the command line flags they stand for.
+fn option_flags(options: &Value) -> Result<Vec<String>, String> {
+    let members = match options {
+        Value::Null => return Ok(Vec::new()),
+        Value::Object(members) => members,
+        _ => return Err(String::from("options must be an object")),
+    };
+    let mut flags = Vec::new();
+    for (name, value) in members {
+        let values = match value {
+            Value::Array(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        for value in values {
+            match value {
+                Value::Boolean(true) => flags.push(format!("--{}", name)),
+                Value::Boolean(false) | Value::Null => {}
+                Value::Number(_) | Value::String(_) => {
+                    let value = match value {
+                        Value::String(string) => string.clone(),
+                        value => value.to_string(),
+                    };
+                    flags.push(format!("--{}={}", name, value))
+                }
+                Value::Array(_) | Value::Object(_) => {
+                    return Err(format!("unsupported value for option '{}'", name))
+                }
+            }
+        }
+    }
+    Ok(flags)
+}
+
+// Handles a `format` request with `params`, returning its result or the code
+// and message of the error to respond with.
+fn format(params: &Value) -> Result<Value, (i64, String)> {
+    let invalid = |message: String| (jsonrpc::INVALID_PARAMS, message);
+    let failed = |message: String| (jsonrpc::REQUEST_FAILED, message);
+
+    let flags = option_flags(params.get("options")).map_err(invalid)?;
+    let (options, matches) = config::parse_flags(&flags).map_err(invalid)?;
+    let path = params.get("path").as_str().map(PathBuf::from);
+    let mut options = match &path {
+        Some(path) => {
+            config::apply(&options, &matches, path).map_err(|error| failed(error.to_string()))?
+        }
+        None => options,
+    };
+
+    let contents = match (params.get("text").as_str(), &path) {
+        (Some(text), _) => text.to_string(),
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .map_err(|error| failed(format!("Error reading '{}': {}", path.display(), error)))?,
+        (None, None) => return Err(invalid(String::from("Either path or text is required"))),
+    };
+
+    let scoped = !options.full_spec && !options.has_range();
+    let path = match path {
+        Some(path) if scoped && !path.is_file() => {
+            return Err(failed(format!(
+                "Cannot scope formatting to the changes to '{}', which does not exist. Pass the full-spec or range option instead.",
+                path.display()
+            )))
+        }
+        Some(path) => path,
+        None if scoped => {
+            return Err(invalid(String::from(
+                "Cannot scope formatting to the changes to a buffer without a path. Pass the full-spec or range option instead.",
+            )))
+        }
+        None => PathBuf::from("<buffer>"),
+    };
+    let unscoped = super::unscope_outside_git(&mut options, &path);
+    let on_base_branch = super::scope_on_base_branch(&mut options, &path);
+    let diff =
+        super::try_scoped_diff(&path, &options).map_err(|error| failed(error.to_string()))?;
+    let diff = super::sanitized_diff_lines(&diff, options.word_diff);
+
+    let rewrapped = super::format_spec(contents.clone(), &diff, &options)
+        .map_err(|error| invalid(error.to_string()))?;
+    let formatted = rewrapped.lines.join("\n");
+    let not_in_git = unscoped.then(|| super::not_in_git_diagnostic(&path));
+    let warnings = rewrapped
+        .report
+        .warnings
+        .iter()
+        .map(|warning| super::warning_diagnostic(warning, &path, &rewrapped.report))
+        .chain(not_in_git)
+        .chain(on_base_branch)
+        .map(|diagnostic| {
+            let line = diagnostic
+                .lines
+                .map_or(Value::Null, |(line, _)| Value::from(line));
+            Value::object(vec![
+                ("code", Value::from(diagnostic.code)),
+                ("line", line),
+                ("message", Value::String(diagnostic.message)),
+            ])
+        })
+        .collect();
+    Ok(Value::object(vec![
+        ("changed", Value::Boolean(formatted != contents)),
+        ("text", Value::String(formatted)),
+        ("warnings", Value::Array(warnings)),
+    ]))
+}
+
+// Serves the requests read from `input` on one connection, writing responses
+// to `output`. Returns whether the client asked the daemon to shut down.
+pub fn serve_connection(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<bool> {
+    while let Some(message) = jsonrpc::read_message(input)? {
+        let message = match jsonrpc::parse(&message) {
+            Ok(message) => message,
+            Err(error) => {
+                jsonrpc::write_message(
+                    output,
+                    &jsonrpc::error_response(Value::Null, jsonrpc::PARSE_ERROR, &error),
+                )?;
+                continue;
+            }
+        };
+        let id = message.get("id").clone();
+        let result = match message.get("method").as_str() {
+            Some("format") => format(message.get("params")),
+            Some("shutdown") => {
+                jsonrpc::write_message(output, &jsonrpc::response(id, Value::Null))?;
+                return Ok(true);
+            }
+            Some(method) => Err((
+                jsonrpc::METHOD_NOT_FOUND,
+                format!("Unsupported method '{}'", method),
+            )),
+            None => Err((jsonrpc::INVALID_REQUEST, String::from("Missing method"))),
+        };
+        // Notifications (requests without an `id`) get no response.
+        if id == Value::Null {
+            continue;
+        }
+        let response = match result {
+            Ok(result) => jsonrpc::response(id, result),
+            Err((code, message)) => jsonrpc::error_response(id, code, &message),
+        };
+        jsonrpc::write_message(output, &response)?;
+    }
+    Ok(false)
+}
+
+// Serves requests on `port` (0 picks any free port) until a client asks us to
+// shut down.
+pub fn run(port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!(
+        "Listening for format requests on {}",
+        listener.local_addr()?
+    );
+    io::stdout().flush()?;
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        std::thread::spawn(move || {
+            let mut output = match stream.try_clone() {
+                Ok(output) => output,
+                Err(_) => return,
+            };
+            let mut input = BufReader::new(stream);
+            // Connections that fail only affect their own client.
+            if let Ok(true) = serve_connection(&mut input, &mut output) {
+                std::process::exit(0);
+            }
+        });
+    }
+
+    unreachable!("`TcpListener::incoming()` never ends")
+}
@@ -0,0 +1,269 @@
+use super::annotations;
+use super::jsonrpc::Value;
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+
+// Every problem specfmt reports about a spec (a warning from formatting, a spec
+// that needs formatting in CI, or an error that stops us from formatting it at
+// all) is a `Diagnostic`, rendered according to `--error-format`. Tools parsing
+// our output can then rely on a single shape for all of them.
+//
+// Most formats print each diagnostic as it is emitted. SARIF is a single
+// document, so its diagnostics are collected until `finish()` prints them.
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+    // `Warning: file:line: message`, surrounded by progress messages.
+    Human,
+    // `file:line: warning[code]: message`, and nothing else.
+    Short,
+    // One JSON object per line, and nothing else.
+    Json,
+    // GitHub Actions workflow commands, which annotate the pull request.
+    Github,
+    // A SARIF 2.1.0 log, for GitHub code scanning and other SARIF consumers.
+    Sarif,
+}
+
+impl ErrorFormat {
+    // Whether progress messages (like "Write succeeded") are printed alongside
+    // diagnostics. Formats meant for machines only print diagnostics.
+    pub fn is_verbose(self) -> bool {
+        matches!(self, ErrorFormat::Human | ErrorFormat::Github)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // A stable, machine-readable name for the kind of problem, like
+    // "overflow".
+    pub code: &'static str,
+    pub file: Option<String>,
+    // The 1-based, inclusive lines the diagnostic is about, if any.
+    pub lines: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, file: Option<String>, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            file,
+            lines: None,
+            message,
+        }
+    }
+
+    pub fn at_lines(mut self, start: usize, end: usize) -> Diagnostic {
+        self.lines = Some((start, end));
+        self
+    }
+
+    // Where the diagnostic is, like `spec.bs:12` or `spec.bs:12-14`, for the
+    // textual formats.
+    fn location(&self) -> Option<String> {
+        let file = self.file.as_deref().unwrap_or("<unknown>");
+        match self.lines {
+            Some((start, end)) if start == end => Some(format!("{}:{}", file, start)),
+            Some((start, end)) => Some(format!("{}:{}-{}", file, start, end)),
+            None => self.file.clone(),
+        }
+    }
+
+    pub fn render(&self, format: ErrorFormat) -> String {
+        match format {
+            ErrorFormat::Human => {
+                let severity = match self.severity {
+                    Severity::Error => "Error",
+                    Severity::Warning => "Warning",
+                };
+                match self.location() {
+                    Some(location) => format!("{}: {}: {}", severity, location, self.message),
+                    None => format!("{}: {}", severity, self.message),
+                }
+            }
+            ErrorFormat::Short => {
+                let severity = format!("{}[{}]", self.severity.name(), self.code);
+                match self.location() {
+                    Some(location) => format!("{}: {}: {}", location, severity, self.message),
+                    None => format!("{}: {}", severity, self.message),
+                }
+            }
+            ErrorFormat::Json => {
+                let string = |value: Option<&str>| match value {
+                    Some(value) => format!("\"{}\"", escape_json(value)),
+                    None => String::from("null"),
+                };
+                let (start, end) = match self.lines {
+                    Some((start, end)) => (start.to_string(), end.to_string()),
+                    None => (String::from("null"), String::from("null")),
+                };
+                format!(
+                    "{{\"severity\":\"{}\",\"code\":\"{}\",\"file\":{},\"line\":{},\"endLine\":{},\"message\":{}}}",
+                    self.severity.name(),
+                    self.code,
+                    string(self.file.as_deref()),
+                    start,
+                    end,
+                    string(Some(&self.message))
+                )
+            }
+            ErrorFormat::Github => annotations::github_annotation(
+                self.severity.name(),
+                self.file.as_deref(),
+                self.lines,
+                &self.message,
+            ),
+            ErrorFormat::Sarif => self.sarif_result().to_string(),
+        }
+    }
+
+    // The SARIF `result` object for the diagnostic. See
+    // https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html.
+    fn sarif_result(&self) -> Value {
+        let mut result = vec![
+            ("ruleId", Value::from(self.code)),
+            ("level", Value::from(self.severity.name())),
+            (
+                "message",
+                Value::object(vec![("text", Value::String(self.message.clone()))]),
+            ),
+        ];
+        if let Some(file) = &self.file {
+            let mut location = vec![(
+                "artifactLocation",
+                Value::object(vec![("uri", Value::String(file.replace('\\', "/")))]),
+            )];
+            if let Some((start, end)) = self.lines {
+                location.push((
+                    "region",
+                    Value::object(vec![
+                        ("startLine", Value::from(start)),
+                        ("endLine", Value::from(end)),
+                    ]),
+                ));
+            }
+            result.push((
+                "locations",
+                Value::Array(vec![Value::object(vec![(
+                    "physicalLocation",
+                    Value::object(location),
+                )])]),
+            ));
+        }
+        Value::object(result)
+    }
+}
+
+thread_local! {
+    // The diagnostics emitted in the SARIF format, which `finish()` prints.
+    static SARIF_DIAGNOSTICS: RefCell<Vec<Diagnostic>> = const { RefCell::new(Vec::new()) };
+}
+
+// Reports `diagnostic` to `out` in `format`.
+pub fn emit(out: &mut dyn Write, diagnostic: &Diagnostic, format: ErrorFormat) {
+    match format {
+        ErrorFormat::Sarif => {
+            SARIF_DIAGNOSTICS.with(|diagnostics| diagnostics.borrow_mut().push(diagnostic.clone()))
+        }
+        _ => writeln!(out, "{}", diagnostic.render(format)).unwrap(),
+    }
+}
+
+// Prints the diagnostics that were collected rather than printed as they were
+// emitted, which is all of them for SARIF, to `out`. Must be called once
+// everything has been reported.
+pub fn finish(out: &mut dyn Write, format: ErrorFormat) -> io::Result<()> {
+    if format != ErrorFormat::Sarif {
+        return Ok(());
+    }
+    let diagnostics = SARIF_DIAGNOSTICS.with(|diagnostics| diagnostics.take());
+
+    let mut rules = Vec::<&str>::new();
+    for diagnostic in &diagnostics {
+        if !rules.contains(&diagnostic.code) {
+            rules.push(diagnostic.code);
+        }
+    }
+    let driver = Value::object(vec![
+        ("name", Value::from("specfmt")),
+        ("version", Value::from(env!("CARGO_PKG_VERSION"))),
+        (
+            "informationUri",
+            Value::from("https://github.com/domfarolino/specfmt"),
+        ),
+        (
+            "rules",
+            Value::Array(
+                rules
+                    .into_iter()
+                    .map(|rule| Value::object(vec![("id", Value::from(rule))]))
+                    .collect(),
+            ),
+        ),
+    ]);
+    let log = Value::object(vec![
+        (
+            "$schema",
+            Value::from("https://json.schemastore.org/sarif-2.1.0.json"),
+        ),
+        ("version", Value::from("2.1.0")),
+        (
+            "runs",
+            Value::Array(vec![Value::object(vec![
+                ("tool", Value::object(vec![("driver", driver)])),
+                (
+                    "results",
+                    Value::Array(diagnostics.iter().map(Diagnostic::sarif_result).collect()),
+                ),
+            ])]),
+        ),
+    ]);
+    writeln!(out, "{}", log)?;
+    out.flush()
+}
+
+pub fn escape_json(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// The message of an error that clap would print, without the "error: " prefix
+// and the usage that follow it.
+pub fn clap_error_message(error: &clap::error::Error) -> String {
+    let rendered = error.render().to_string();
+    let first_line = rendered.lines().next().unwrap_or_default();
+    first_line
+        .strip_prefix("error: ")
+        .unwrap_or(first_line)
+        .to_string()
+}
@@ -0,0 +1,246 @@
+use std::fs::read_dir;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+// Finds the spec to format when none is given, by looking for the files in a
+// directory that match a list of patterns. Patterns are tried in order, and the
+// first one matching exactly one file wins. By default, that's "source" (for
+// whatwg/html), followed by the unique .bs file.
+
+pub const DEFAULT_PATTERNS: &str = "source,*.bs";
+
+// Whether `name` matches `pattern`, in which `*` matches any number of
+// characters and `?` matches exactly one.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // The classic greedy wildcard matcher, which backtracks to the most recent
+    // `*` on a mismatch.
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// A file considered during discovery, along with the index of the first
+// pattern it matches (if any).
+pub struct Candidate {
+    pub path: PathBuf,
+    pub pattern: Option<usize>,
+}
+
+pub struct Discovery {
+    pub candidates: Vec<Candidate>,
+    // The spec that was found, or why none was.
+    pub spec: Result<PathBuf, String>,
+}
+
+pub fn discover(directory: &Path, patterns: &[String]) -> Discovery {
+    let entries = match read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) => {
+            return Discovery {
+                candidates: Vec::new(),
+                spec: Err(format!(
+                    "Must specify filename: cannot read directory '{}': {}",
+                    directory.display(),
+                    error
+                )),
+            }
+        }
+    };
+
+    let mut candidates: Vec<Candidate> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            Candidate {
+                pattern: patterns
+                    .iter()
+                    .position(|pattern| matches_pattern(pattern, &name)),
+                path,
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.path.cmp(&b.path));
+
+    for (i, pattern) in patterns.iter().enumerate() {
+        let matches: Vec<&Candidate> = candidates
+            .iter()
+            .filter(|candidate| candidate.pattern == Some(i))
+            .collect();
+        if matches.len() == 1 {
+            let spec = Ok(matches[0].path.clone());
+            return Discovery { candidates, spec };
+        }
+        if matches.len() > 1 {
+            let spec = Err(format!(
+                "Must specify filename: directory contains multiple files matching '{}'",
+                pattern
+            ));
+            return Discovery { candidates, spec };
+        }
+    }
+
+    let spec = Err(format!(
+        "Must specify filename: directory doesn't contain a file matching '{}'",
+        patterns.join("' or '")
+    ));
+    Discovery { candidates, spec }
+}
+
+// Finds every spec in `directory` and its subdirectories, i.e., every file that
+// matches any of `patterns`, for repositories that hold several specs. Hidden
+// directories (like `.git`) are skipped.
+pub fn discover_recursive(directory: &Path, patterns: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut specs = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in read_dir(&directory)? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            if path.is_dir() {
+                if !name.starts_with('.') {
+                    directories.push(path);
+                }
+            } else if path.is_file()
+                && patterns
+                    .iter()
+                    .any(|pattern| matches_pattern(pattern, &name))
+            {
+                specs.push(path);
+            }
+        }
+    }
+    specs.sort();
+    Ok(specs)
+}
+
+// Explains, for each candidate, why it was or wasn't chosen as the spec.
+pub fn describe(discovery: &Discovery, patterns: &[String]) -> Vec<String> {
+    let chosen = discovery.spec.as_ref().ok();
+    let chosen_pattern = discovery
+        .candidates
+        .iter()
+        .find(|candidate| Some(&candidate.path) == chosen)
+        .and_then(|candidate| candidate.pattern);
+
+    discovery
+        .candidates
+        .iter()
+        .map(|candidate| {
+            let reason = match candidate.pattern {
+                None => String::from("matches no pattern"),
+                Some(i) if Some(&candidate.path) == chosen => {
+                    format!("matches '{}' (chosen)", patterns[i])
+                }
+                Some(i) => match chosen_pattern {
+                    Some(chosen) if chosen < i => format!(
+                        "matches '{}', but '{}' matched first",
+                        patterns[i], patterns[chosen]
+                    ),
+                    _ => format!("matches '{}', along with other files", patterns[i]),
+                },
+            };
+            format!("{}: {}", candidate.path.display(), reason)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    fn patterns(patterns: &str) -> Vec<String> {
+        patterns.split(',').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches_pattern("*.bs", "index.bs"));
+        assert!(matches_pattern("*.src.html", "fetch.src.html"));
+        assert!(matches_pattern("spec-?.bs", "spec-2.bs"));
+        assert!(!matches_pattern("spec-?.bs", "spec-10.bs"));
+        assert!(!matches_pattern("*.bs", "index.bs.orig"));
+        assert!(matches_pattern("*a*b", "aXbab"));
+    }
+
+    #[test]
+    fn first_pattern_matching_one_file_wins() {
+        let directory = tempfile::tempdir().unwrap();
+        for name in ["index.bs", "fetch.src.html", "notes.txt"] {
+            fs::write(directory.path().join(name), "").unwrap();
+        }
+        let patterns = patterns("source,*.src.html,*.bs");
+        let discovery = discover(directory.path(), &patterns);
+        assert_eq!(discovery.spec, Ok(directory.path().join("fetch.src.html")));
+        let described = describe(&discovery, &patterns);
+        let prefix = directory.path().join("").display().to_string();
+        let described: Vec<&str> = described
+            .iter()
+            .map(|description| description.strip_prefix(&prefix).unwrap())
+            .collect();
+        assert_eq!(
+            described,
+            [
+                "fetch.src.html: matches '*.src.html' (chosen)",
+                "index.bs: matches '*.bs', but '*.src.html' matched first",
+                "notes.txt: matches no pattern",
+            ]
+        );
+
+        fs::write(directory.path().join("xhr.src.html"), "").unwrap();
+        let discovery = discover(directory.path(), &patterns);
+        assert_eq!(
+            discovery.spec,
+            Err(String::from(
+                "Must specify filename: directory contains multiple files matching '*.src.html'"
+            ))
+        );
+    }
+
+    #[test]
+    fn every_spec_in_subdirectories() {
+        let directory = tempfile::tempdir().unwrap();
+        for subdirectory in ["fetch", "fetch/images", ".git"] {
+            fs::create_dir(directory.path().join(subdirectory)).unwrap();
+        }
+        for name in [
+            "index.bs",
+            "fetch/fetch.bs",
+            "fetch/images/logo.svg",
+            ".git/old.bs",
+        ] {
+            fs::write(directory.path().join(name), "").unwrap();
+        }
+        let specs = discover_recursive(directory.path(), &patterns(DEFAULT_PATTERNS)).unwrap();
+        assert_eq!(
+            specs,
+            [
+                directory.path().join("fetch/fetch.bs"),
+                directory.path().join("index.bs"),
+            ]
+        );
+        let specs = discover_recursive(directory.path(), &patterns("*.svg")).unwrap();
+        assert_eq!(specs, [directory.path().join("fetch/images/logo.svg")]);
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+// The ways that specfmt can fail. Every failure is surfaced as one of these,
+// and reported as a diagnostic (or, for usage errors in the human format, by
+// clap along with the usage) before exiting with `EXIT_ERROR`, rather than
+// panicking with a backtrace.
+#[derive(Debug)]
+pub enum SpecfmtError {
+    // An I/O `error` while doing `action`, like "opening file".
+    Io {
+        action: &'static str,
+        error: io::Error,
+    },
+    // Running git failed, or git can't tell us what we need, like the changes
+    // to scope formatting to.
+    #[cfg_attr(not(feature = "git"), allow(dead_code))]
+    Git(String),
+    // Something we read, like a spec or the output of git, is malformed.
+    Parse(String),
+    // A `<!-- specfmt: wrap=COLUMNS -->` comment in the spec is malformed.
+    WrapDirective(String),
+    // The Bikeshed metadata block of the spec has a value we don't understand.
+    Metadata(String),
+    // The configuration `file` is invalid.
+    Config {
+        file: PathBuf,
+        message: String,
+    },
+    // The command line is invalid, or doesn't lead to a spec.
+    Usage(clap::error::Error),
+}
+
+impl SpecfmtError {
+    pub fn io(action: &'static str, error: io::Error) -> SpecfmtError {
+        SpecfmtError::Io { action, error }
+    }
+
+    // The code to report this error with, for errors that don't get a more
+    // specific one from what we were doing when they happened.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SpecfmtError::Io { .. } => "io",
+            SpecfmtError::Git(_) => "git",
+            SpecfmtError::Parse(_) => "parse",
+            SpecfmtError::WrapDirective(_) => "wrap-directive",
+            SpecfmtError::Metadata(_) => "metadata",
+            SpecfmtError::Config { .. } => "config",
+            SpecfmtError::Usage(_) => "usage",
+        }
+    }
+}
+
+impl fmt::Display for SpecfmtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecfmtError::Io { action, error } => write!(f, "Error {}: {}", action, error),
+            SpecfmtError::Git(message)
+            | SpecfmtError::Parse(message)
+            | SpecfmtError::WrapDirective(message)
+            | SpecfmtError::Metadata(message) => f.write_str(message),
+            SpecfmtError::Config { file, message } => write!(
+                f,
+                "Invalid configuration in '{}': {}",
+                file.display(),
+                message
+            ),
+            SpecfmtError::Usage(error) => {
+                f.write_str(&super::diagnostics::clap_error_message(error))
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpecfmtError {}
+
+impl From<clap::error::Error> for SpecfmtError {
+    fn from(error: clap::error::Error) -> SpecfmtError {
+        SpecfmtError::Usage(error)
+    }
+}
@@ -0,0 +1,818 @@
+use super::diff;
+use super::error::SpecfmtError;
+use git2::ApplyLocation;
+use git2::BranchType;
+use git2::DiffFindOptions;
+use git2::DiffFormat;
+use git2::DiffOptions;
+use git2::Repository;
+use git2::Status;
+use git2::Tree;
+use std::path::Path;
+
+// Everything that reads from git, to scope formatting to the changes in a
+// repository. This uses libgit2 rather than running the `git` binary, so it works
+// without git installed, and isn't affected by user configuration that changes
+// how git prints things. Only available with the `git` feature.
+
+fn git_error(error: git2::Error) -> SpecfmtError {
+    SpecfmtError::Git(error.message().to_string())
+}
+
+// The directory of the file at `path`, for discovering its repository.
+fn directory_of(path: &Path) -> &Path {
+    match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    }
+}
+
+// Opens the repository with the file at `path` in its work tree, returning it
+// along with the path of the file relative to the top level of the work tree,
+// with `/` separators, which is how git names files in pathspecs, diffs and the
+// index. The top level comes from the repository (honoring `core.worktree`)
+// rather than from where specfmt runs or where the spec is, so specs in
+// subdirectories (like `spec/source`) are found however specfmt is invoked.
+fn open(path: &Path) -> Result<(Repository, String), SpecfmtError> {
+    // A work tree configured with `core.worktree` or `GIT_WORK_TREE` may have no
+    // `.git` above the spec to discover, so fall back to the repository that git
+    // itself would use from here, honoring `GIT_DIR` and `GIT_WORK_TREE`.
+    let repository = Repository::discover(directory_of(path))
+        .or_else(|error| Repository::open_from_env().map_err(|_| error))
+        .map_err(git_error)?;
+    let workdir = repository.workdir().ok_or_else(|| {
+        SpecfmtError::Git(String::from(
+            "The spec is in a bare repository, which has no work tree",
+        ))
+    })?;
+    let not_in_work_tree = || {
+        SpecfmtError::Git(format!(
+            "'{}' is not in the work tree of the repository at '{}'",
+            path.display(),
+            workdir.display()
+        ))
+    };
+    let workdir = workdir.canonicalize().map_err(|_| not_in_work_tree())?;
+    let directory = directory_of(path)
+        .canonicalize()
+        .map_err(|_| not_in_work_tree())?;
+    let relative = directory
+        .strip_prefix(&workdir)
+        .map_err(|_| not_in_work_tree())?
+        .join(path.file_name().ok_or_else(not_in_work_tree)?);
+    let relative = relative.to_str().ok_or_else(|| {
+        SpecfmtError::Git(format!(
+            "The path of '{}' in the repository is not valid UTF-8",
+            path.display()
+        ))
+    })?;
+    let relative = relative.replace(std::path::MAIN_SEPARATOR, "/");
+    Ok((repository, relative))
+}
+
+// Returns the tree of the commit that `HEAD` points to, or `None` if there are
+// no commits yet.
+fn head_tree(repository: &Repository) -> Result<Option<Tree<'_>>, SpecfmtError> {
+    match repository.head() {
+        Ok(head) => Ok(Some(head.peel_to_tree().map_err(git_error)?)),
+        Err(error) if error.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(error) => Err(git_error(error)),
+    }
+}
+
+// Options for diffing without context, like `git diff -U0`.
+fn diff_options() -> DiffOptions {
+    let mut options = DiffOptions::new();
+    options
+        .context_lines(0)
+        .interhunk_lines(0)
+        .old_prefix("a/")
+        .new_prefix("b/");
+    options
+}
+
+// Renders `diff` as a patch, like `git diff` prints it. Lines that aren't UTF-8
+// are converted lossily. The output should be filtered by
+// `sanitized_diff_lines()`.
+fn patch_text(diff: &git2::Diff) -> Result<String, SpecfmtError> {
+    let mut text = String::new();
+    diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
+        if let origin @ ('+' | '-' | ' ') = line.origin() {
+            text.push(origin);
+        }
+        text.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })
+    .map_err(git_error)?;
+    Ok(text)
+}
+
+// Computes the diff of just the file at `relative` with `diff_with`, given the
+// options to diff with, and renders it with `patch_text()`. If the file was
+// added, it may have been renamed (like from `index.bs` to `spec.bs`), so we
+// diff everything with rename detection to find the file it came from, and
+// render the diff against that instead. Otherwise, all of the spec would count
+// as changed.
+fn spec_patch<'repository>(
+    relative: &str,
+    diff_with: impl Fn(&mut DiffOptions) -> Result<git2::Diff<'repository>, git2::Error>,
+) -> Result<String, SpecfmtError> {
+    let mut options = diff_options();
+    options.pathspec(relative).disable_pathspec_match(true);
+    let diff = diff_with(&mut options).map_err(git_error)?;
+    if !diff
+        .deltas()
+        .any(|delta| delta.status() == git2::Delta::Added)
+    {
+        return patch_text(&diff);
+    }
+
+    let mut diff = diff_with(&mut diff_options()).map_err(git_error)?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))
+        .map_err(git_error)?;
+    for (index, delta) in diff.deltas().enumerate() {
+        if delta.new_file().path() != Some(Path::new(relative)) {
+            continue;
+        }
+        if let Some(mut patch) = git2::Patch::from_diff(&diff, index).map_err(git_error)? {
+            let text = patch.to_buf().map_err(git_error)?;
+            return Ok(String::from_utf8_lossy(&text).into_owned());
+        }
+    }
+    Ok(String::new())
+}
+
+// Returns whether the file at `path` is in the work tree of a git repository.
+pub fn is_in_repository(path: &Path) -> bool {
+    open(path).is_ok()
+}
+
+pub fn assert_no_uncommitted_changes(path: &Path) -> Result<(), SpecfmtError> {
+    assert!(path.is_file());
+    // A spec outside of a repository has no uncommitted changes to protect.
+    let (repository, relative) = match open(path) {
+        Ok(opened) => opened,
+        Err(_) => return Ok(()),
+    };
+
+    let status = repository
+        .status_file(Path::new(&relative))
+        .map_err(git_error)?;
+    // This means that the spec we're targeting does not have uncommitted
+    // changes, so we're safe to proceed with rewrapping.
+    if status == Status::CURRENT || status == Status::IGNORED {
+        return Ok(());
+    }
+    Err(SpecfmtError::Git(String::from(
+        "Spec has uncommitted changes. Please commit your changes and try again.",
+    )))
+}
+
+// The base branches to try after the upstream branch and the default branch of
+// `origin`, when none are configured.
+const DEFAULT_BASE_BRANCHES: [&str; 2] = ["main", "master"];
+
+// Returns the upstream branch that the current branch tracks, if it's a branch
+// of another name (like `upstream/main` for `some-feature` in a fork), which is
+// what the current branch is meant to be merged into. The same-named branch on
+// a remote (like `origin/some-feature`) is just where the current branch was
+// pushed, which only differs by the commits that are yet to be pushed.
+fn upstream_base_branch(repository: &Repository) -> Option<String> {
+    let head = repository.head().ok().filter(|head| head.is_branch())?;
+    let merge = repository.branch_upstream_merge(head.name()?).ok()?;
+    if merge.as_str() == head.name() {
+        return None;
+    }
+    let upstream = git2::Branch::wrap(head).upstream().ok()?;
+    upstream.name().ok()?.map(str::to_string)
+}
+
+// Returns the branch that `origin/HEAD` points to, like `origin/main`.
+fn origin_head(repository: &Repository) -> Option<String> {
+    let origin_head = repository.find_reference("refs/remotes/origin/HEAD").ok()?;
+    let target = origin_head
+        .symbolic_target()?
+        .strip_prefix("refs/remotes/")?;
+    Some(target.to_string())
+}
+
+// Returns the base branches to try when none are configured: the upstream
+// branch that the current branch tracks (see `upstream_base_branch()`), the
+// default branch of the `origin` remote (which may be named anything, like
+// `trunk`), preferring a local branch of the same name, the default branch of
+// the bare repository that a linked worktree belongs to, then
+// `DEFAULT_BASE_BRANCHES`.
+fn default_base_branches(repository: &Repository) -> Vec<String> {
+    let mut base_branches: Vec<String> = upstream_base_branch(repository).into_iter().collect();
+    base_branches.extend(default_branches(repository));
+    base_branches
+}
+
+// Returns the base branches of `default_base_branches()` that don't depend on
+// the current branch, i.e., all but its upstream branch.
+fn default_branches(repository: &Repository) -> Vec<String> {
+    let mut base_branches = Vec::new();
+    if let Some(origin_head) = origin_head(repository) {
+        if let Some(branch) = origin_head.strip_prefix("origin/") {
+            base_branches.push(branch.to_string());
+        }
+        base_branches.push(origin_head);
+    }
+
+    // Spec editors who keep one linked worktree per pull request often check
+    // them out of a bare clone, which has no remote-tracking branches (so no
+    // `origin/HEAD`), but whose own `HEAD` names the default branch it was
+    // cloned from.
+    if let Some(common_head) = repository
+        .is_worktree()
+        .then(|| Repository::open(repository.commondir()).ok())
+        .flatten()
+        .filter(|common| common.is_bare())
+        .and_then(|common| common.head().ok()?.shorthand().map(str::to_string))
+    {
+        base_branches.push(common_head);
+    }
+    for branch in DEFAULT_BASE_BRANCHES.map(str::to_string) {
+        if !base_branches.contains(&branch) {
+            base_branches.push(branch);
+        }
+    }
+    base_branches
+}
+
+// Finds the base branch to compare the current branch of `repository` with:
+// the first of `base_branches` that exists (or of `default_base_branches()` if
+// there are none), which may name remote branches like `upstream/main`.
+// Returns it with its name, or fails if none of them exist, since that
+// configuration is considered invalid.
+fn base_branch<'repository>(
+    repository: &'repository Repository,
+    base_branches: &[String],
+) -> Result<(String, git2::Branch<'repository>), SpecfmtError> {
+    let base_branches = if base_branches.is_empty() {
+        default_base_branches(repository)
+    } else {
+        base_branches.to_vec()
+    };
+    let base_branch = base_branches.iter().find_map(|candidate| {
+        repository
+            .find_branch(candidate, BranchType::Local)
+            .or_else(|_| repository.find_branch(candidate, BranchType::Remote))
+            .ok()
+            .map(|branch| (candidate.clone(), branch))
+    });
+    base_branch.ok_or_else(|| {
+        let current_branch = repository
+            .head()
+            .ok()
+            .and_then(|head| head.shorthand().map(str::to_string))
+            .unwrap_or_else(|| String::from("HEAD"));
+        SpecfmtError::Git(format!(
+            "Cannot find a base branch ('{}') with which to compare the current branch '{}' of the spec",
+            base_branches.join("', '"),
+            current_branch
+        ))
+    })
+}
+
+// Returns the name of the branch that `branch` (a local or remote-tracking
+// branch of `repository`) is, or tracks on its remote, like `main` for both
+// `main` and `origin/main`.
+fn short_name(repository: &Repository, branch: &git2::Branch) -> Option<String> {
+    let reference = branch.get().name()?;
+    if let Some(name) = reference.strip_prefix("refs/heads/") {
+        return Some(name.to_string());
+    }
+    let remote = repository.branch_remote_name(reference).ok()?;
+    let name = branch.name().ok()??;
+    Some(
+        name.strip_prefix(remote.as_str()?)?
+            .strip_prefix('/')?
+            .to_string(),
+    )
+}
+
+// Returns the name of the base branch (chosen like `git_diff()` does, but never
+// the upstream branch) if the spec at `path` is on it, i.e., the current branch
+// is that base branch or the default branch of `origin`, rather than a branch
+// of its own that was pushed somewhere, so there are no changes to scope
+// formatting to. This is the case when formatting on `main` itself.
+pub fn on_base_branch(path: &Path, base_branches: &[String]) -> Option<String> {
+    let (repository, _relative) = open(path).ok()?;
+    let head = repository.head().ok().filter(|head| head.is_branch())?;
+    let current_branch = head.shorthand()?.to_string();
+    let base_branches = if base_branches.is_empty() {
+        default_branches(&repository)
+    } else {
+        base_branches.to_vec()
+    };
+    let (name, base_branch) = base_branch(&repository, &base_branches).ok()?;
+    let origin_head = origin_head(&repository);
+    let default_branch = origin_head
+        .as_deref()
+        .and_then(|origin_head| origin_head.strip_prefix("origin/"));
+    let is_current = short_name(&repository, &base_branch).as_deref() == Some(&current_branch)
+        || default_branch == Some(&current_branch);
+    is_current.then_some(name)
+}
+
+// If there are no errors, this returns the computed diff of the target spec's
+// current branch and base branch (chosen by `base_branch()`). The output should
+// be filtered by `sanitized_diff_lines()`.
+pub fn git_diff(path: &Path, base_branches: &[String]) -> Result<String, SpecfmtError> {
+    assert!(path.is_file());
+    let (repository, relative) = open(path)?;
+
+    // Get the tree of the commit that the spec is currently on, and of the base
+    // branch to compare it with.
+    let current_tree = repository
+        .head()
+        .and_then(|head| head.peel_to_tree())
+        .map_err(git_error)?;
+    let (_name, base_branch) = base_branch(&repository, base_branches)?;
+    let base_tree = base_branch.get().peel_to_tree().map_err(git_error)?;
+
+    // Finally, compute the diff between the current branch and the base branch.
+    // Return the diff so we can inform the rewrapper of which lines to format
+    // (as to avoid rewrapping the *entire* spec).
+    spec_patch(&relative, |options| {
+        repository.diff_tree_to_tree(Some(&base_tree), Some(&current_tree), Some(options))
+    })
+}
+
+// Like `git_diff()`, but computes the diff between `commit`, which can be any
+// commit-ish (like a tag or `HEAD~3`), and the current commit.
+pub fn git_diff_since(path: &Path, commit: &str) -> Result<String, SpecfmtError> {
+    assert!(path.is_file());
+    let (repository, relative) = open(path)?;
+
+    let base_tree = repository
+        .revparse_single(commit)
+        .and_then(|object| object.peel_to_tree())
+        .map_err(|_| {
+            SpecfmtError::Git(format!(
+                "Cannot find the commit '{}' to compare with",
+                commit
+            ))
+        })?;
+    let current_tree = head_tree(&repository)?;
+    spec_patch(&relative, |options| {
+        repository.diff_tree_to_tree(Some(&base_tree), current_tree.as_ref(), Some(options))
+    })
+}
+
+// Like `git_diff()`, but computes the diff of the changes to the spec that are
+// staged in the index.
+pub fn git_diff_staged(path: &Path) -> Result<String, SpecfmtError> {
+    assert!(path.is_file());
+    let (repository, relative) = open(path)?;
+
+    let current_tree = head_tree(&repository)?;
+    spec_patch(&relative, |options| {
+        repository.diff_tree_to_index(current_tree.as_ref(), None, Some(options))
+    })
+}
+
+// Like `git_diff()`, but computes the diff of the uncommitted changes to the
+// spec, staged or not.
+pub fn git_diff_working_tree(path: &Path) -> Result<String, SpecfmtError> {
+    assert!(path.is_file());
+    let (repository, relative) = open(path)?;
+
+    let current_tree = head_tree(&repository)?;
+    spec_patch(&relative, |options| {
+        repository.diff_tree_to_workdir_with_index(current_tree.as_ref(), Some(options))
+    })
+}
+
+// Returns the path of the file at `path` relative to the root of the git
+// repository it is in, which is how `git apply` expects patches to name files,
+// or `None` if it's not in a repository.
+pub fn repository_path(path: &Path) -> Option<String> {
+    open(path).ok().map(|(_repository, relative)| relative)
+}
+
+// Stages the changes that formatting made to the spec at `path`, so that a
+// commit made right after formatting with `--staged` contains the formatted
+// content. Rather than adding the whole file to the index, we apply only the
+// formatting changes to it, which leaves any unstaged changes to the spec
+// unstaged.
+pub fn restage(path: &Path, original: &str, formatted: &str) -> Result<(), SpecfmtError> {
+    let cannot_stage = || {
+        SpecfmtError::Git(format!(
+            "Could not stage the formatting changes to '{}'. Please review and stage them manually.",
+            path.display()
+        ))
+    };
+    let (repository, repository_path) = open(path)?;
+    let patch = diff::unified_diff(original, formatted, &repository_path);
+    if patch.is_empty() {
+        return Ok(());
+    }
+
+    // libgit2 only parses patches with a `diff --git` header.
+    let patch = format!(
+        "diff --git a/{} b/{}\n{}",
+        repository_path, repository_path, patch
+    );
+    let patch = git2::Diff::from_buffer(patch.as_bytes()).map_err(git_error)?;
+    // This fails when unstaged changes are too close to the formatting changes
+    // for the patch to apply to the staged version of the spec.
+    repository
+        .apply(&patch, ApplyLocation::Index, None)
+        .map_err(|_| cannot_stage())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use git2::Oid;
+    use git2::RepositoryInitOptions;
+    use git2::Signature;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    // Creates a repository with no commits on `main` in a temporary directory,
+    // which is deleted when the returned `TempDir` is dropped.
+    fn init() -> (TempDir, Repository) {
+        let directory = tempfile::tempdir().unwrap();
+        let repository = Repository::init_opts(
+            directory.path(),
+            RepositoryInitOptions::new().initial_head("main"),
+        )
+        .unwrap();
+        (directory, repository)
+    }
+
+    // The path of the file `name` in the work tree of `repository`.
+    fn path(repository: &Repository, name: &str) -> PathBuf {
+        repository.workdir().unwrap().join(name)
+    }
+
+    // Writes `contents` to the file `name` and stages it.
+    fn stage(repository: &Repository, name: &str, contents: &str) {
+        fs::write(path(repository, name), contents).unwrap();
+        let mut index = repository.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+    }
+
+    // Deletes the file `name` and stages its deletion.
+    fn remove(repository: &Repository, name: &str) {
+        fs::remove_file(path(repository, name)).unwrap();
+        let mut index = repository.index().unwrap();
+        index.remove_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+    }
+
+    // The contents of the file `name` staged in the index.
+    fn staged(repository: &Repository, name: &str) -> String {
+        let mut index = repository.index().unwrap();
+        index.read(true).unwrap();
+        let entry = index.get_path(Path::new(name), 0).unwrap();
+        let blob = repository.find_blob(entry.id).unwrap();
+        String::from_utf8(blob.content().to_vec()).unwrap()
+    }
+
+    // Commits what's staged onto `HEAD`.
+    fn commit(repository: &Repository) -> Oid {
+        let mut index = repository.index().unwrap();
+        let tree = repository.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Spec Editor", "editor@example.com").unwrap();
+        let parent = head_tree(repository)
+            .unwrap()
+            .map(|_| repository.head().unwrap().peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        repository
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "Edit",
+                &tree,
+                &parents,
+            )
+            .unwrap()
+    }
+
+    // Checks out a new branch `name` at `HEAD`.
+    fn checkout_branch(repository: &Repository, name: &str) {
+        let head = repository.head().unwrap().peel_to_commit().unwrap();
+        repository.branch(name, &head, false).unwrap();
+        repository
+            .set_head(&format!("refs/heads/{}", name))
+            .unwrap();
+    }
+
+    // Makes the current branch track `upstream` (like `origin/main`) of a
+    // remote, as if it was pushed there or branched off of it.
+    fn track(repository: &Repository, upstream: &str) {
+        let (remote, _branch) = upstream.split_once('/').unwrap();
+        if repository.find_remote(remote).is_err() {
+            repository
+                .remote(remote, &format!("https://example.com/{}/spec.git", remote))
+                .unwrap();
+        }
+        let head = repository.head().unwrap();
+        let target = head.target().unwrap();
+        repository
+            .reference(
+                &format!("refs/remotes/{}", upstream),
+                target,
+                false,
+                "fetch",
+            )
+            .unwrap();
+        git2::Branch::wrap(head)
+            .set_upstream(Some(upstream))
+            .unwrap();
+    }
+
+    #[test]
+    fn upstream_of_another_name_is_the_first_base_branch() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", "<p>Spec</p>\n");
+        commit(&repository);
+        checkout_branch(&repository, "some-feature");
+        track(&repository, "upstream/main");
+        assert_eq!(
+            default_base_branches(&repository),
+            ["upstream/main", "main", "master"]
+        );
+    }
+
+    #[test]
+    fn same_named_upstream_is_not_a_base_branch() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", "<p>Spec</p>\n");
+        commit(&repository);
+        checkout_branch(&repository, "some-feature");
+        track(&repository, "origin/some-feature");
+        assert_eq!(default_base_branches(&repository), ["main", "master"]);
+        let (name, _branch) = base_branch(&repository, &[]).unwrap();
+        assert_eq!(name, "main");
+    }
+
+    const SPEC: &str = "<p>One</p>\n<p>Two</p>\n<p>Three</p>\n<p>Four</p>\n<p>Five</p>\n";
+
+    #[test]
+    fn renamed_spec_is_diffed_against_its_old_name() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", SPEC);
+        commit(&repository);
+        checkout_branch(&repository, "rename");
+        remove(&repository, "index.bs");
+        stage(&repository, "spec.bs", &SPEC.replace("Three", "3"));
+        commit(&repository);
+
+        let diff = git_diff(&path(&repository, "spec.bs"), &[String::from("main")]).unwrap();
+        assert!(diff.contains("rename from index.bs"));
+        assert!(diff.contains("-<p>Three</p>\n+<p>3</p>\n"));
+        assert!(!diff.contains("+<p>One</p>"));
+    }
+
+    #[test]
+    fn diff_since_commit() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", SPEC);
+        commit(&repository);
+        stage(&repository, "index.bs", &SPEC.replace("Two", "2"));
+        commit(&repository);
+        stage(
+            &repository,
+            "index.bs",
+            &SPEC.replace("Two", "2").replace("Four", "4"),
+        );
+        commit(&repository);
+
+        let spec = path(&repository, "index.bs");
+        let diff = git_diff_since(&spec, "HEAD~1").unwrap();
+        assert!(diff.contains("+<p>4</p>"));
+        assert!(!diff.contains("+<p>2</p>"));
+        let diff = git_diff_since(&spec, "HEAD~2").unwrap();
+        assert!(diff.contains("+<p>2</p>") && diff.contains("+<p>4</p>"));
+        assert!(git_diff_since(&spec, "no-such-commit").is_err());
+    }
+
+    #[test]
+    fn diff_staged_and_working_tree() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", SPEC);
+        commit(&repository);
+        stage(&repository, "index.bs", &SPEC.replace("One", "1"));
+        let spec = path(&repository, "index.bs");
+        fs::write(&spec, SPEC.replace("One", "1").replace("Five", "5")).unwrap();
+
+        let staged = git_diff_staged(&spec).unwrap();
+        assert!(staged.contains("+<p>1</p>"));
+        assert!(!staged.contains("+<p>5</p>"));
+        let working_tree = git_diff_working_tree(&spec).unwrap();
+        assert!(working_tree.contains("+<p>1</p>") && working_tree.contains("+<p>5</p>"));
+    }
+
+    #[test]
+    fn uncommitted_changes() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", SPEC);
+        commit(&repository);
+        let spec = path(&repository, "index.bs");
+        assert!(assert_no_uncommitted_changes(&spec).is_ok());
+
+        fs::write(&spec, SPEC.replace("One", "1")).unwrap();
+        assert!(assert_no_uncommitted_changes(&spec).is_err());
+        stage(&repository, "index.bs", &SPEC.replace("One", "1"));
+        assert!(assert_no_uncommitted_changes(&spec).is_err());
+        commit(&repository);
+        assert!(assert_no_uncommitted_changes(&spec).is_ok());
+
+        let outside = tempfile::tempdir().unwrap();
+        let spec = outside.path().join("index.bs");
+        fs::write(&spec, SPEC).unwrap();
+        assert!(assert_no_uncommitted_changes(&spec).is_ok());
+    }
+
+    #[test]
+    fn restage_leaves_unstaged_changes_unstaged() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", SPEC);
+        commit(&repository);
+        // The staged change needs formatting, and the last line has an unstaged
+        // change.
+        stage(&repository, "index.bs", &SPEC.replace("One", "  One"));
+        let spec = path(&repository, "index.bs");
+        let original = SPEC.replace("One", "  One").replace("Five", "5");
+        let formatted = SPEC.replace("Five", "5");
+        fs::write(&spec, &formatted).unwrap();
+
+        restage(&spec, &original, &formatted).unwrap();
+        assert_eq!(staged(&repository, "index.bs"), SPEC);
+        assert_eq!(fs::read_to_string(&spec).unwrap(), formatted);
+    }
+
+    // Makes the repository look like a clone of `origin`, whose default branch is
+    // `main`, with the current branch being `main`.
+    fn clone_of_origin(repository: &Repository) {
+        stage(repository, "index.bs", SPEC);
+        commit(repository);
+        track(repository, "origin/main");
+        repository
+            .reference_symbolic(
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/main",
+                false,
+                "clone",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn pushed_feature_branch_is_not_on_base_branch() {
+        let (_directory, repository) = init();
+        clone_of_origin(&repository);
+        checkout_branch(&repository, "some-feature");
+        stage(&repository, "index.bs", &SPEC.replace("One", "1"));
+        commit(&repository);
+        track(&repository, "origin/some-feature");
+
+        let spec = path(&repository, "index.bs");
+        assert_eq!(on_base_branch(&spec, &[]), None);
+        assert_eq!(on_base_branch(&spec, &[String::from("origin/main")]), None);
+    }
+
+    #[test]
+    fn main_is_on_base_branch() {
+        let (_directory, repository) = init();
+        clone_of_origin(&repository);
+
+        let spec = path(&repository, "index.bs");
+        assert_eq!(on_base_branch(&spec, &[]), Some(String::from("main")));
+        assert_eq!(
+            on_base_branch(&spec, &[String::from("origin/main")]),
+            Some(String::from("origin/main"))
+        );
+        // Even with commits that are yet to be pushed.
+        stage(&repository, "index.bs", &SPEC.replace("One", "1"));
+        commit(&repository);
+        assert_eq!(on_base_branch(&spec, &[]), Some(String::from("main")));
+    }
+
+    #[test]
+    fn user_configuration_does_not_change_the_patch() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", SPEC);
+        commit(&repository);
+        let mut config = repository.config().unwrap();
+        config.set_bool("diff.noprefix", true).unwrap();
+        config.set_bool("diff.mnemonicPrefix", true).unwrap();
+        config.set_str("color.ui", "always").unwrap();
+        config.set_i32("diff.context", 10).unwrap();
+        config.set_str("diff.algorithm", "patience").unwrap();
+        let spec = path(&repository, "index.bs");
+        fs::write(&spec, SPEC.replace("Three", "3")).unwrap();
+
+        let diff = git_diff_working_tree(&spec).unwrap();
+        assert!(diff.contains("--- a/index.bs\n+++ b/index.bs\n"));
+        assert!(diff.contains("@@ -3 +3 @@\n-<p>Three</p>\n+<p>3</p>\n"));
+        assert!(!diff.contains('\x1b'));
+    }
+
+    #[test]
+    fn first_configured_base_branch_that_exists() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", SPEC);
+        commit(&repository);
+        checkout_branch(&repository, "some-feature");
+        let base_branches = [String::from("upstream/main"), String::from("main")];
+        let (name, _branch) = base_branch(&repository, &base_branches).unwrap();
+        assert_eq!(name, "main");
+
+        track(&repository, "upstream/main");
+        let (name, _branch) = base_branch(&repository, &base_branches).unwrap();
+        assert_eq!(name, "upstream/main");
+
+        let error = base_branch(&repository, &[String::from("trunk")]).err();
+        assert!(error.unwrap().to_string().contains("('trunk')"));
+    }
+
+    #[test]
+    fn default_branch_of_origin() {
+        let (_directory, repository) = init();
+        stage(&repository, "index.bs", SPEC);
+        commit(&repository);
+        checkout_branch(&repository, "some-feature");
+        track(&repository, "origin/trunk");
+        track(&repository, "origin/some-feature");
+        repository
+            .reference_symbolic(
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/trunk",
+                false,
+                "clone",
+            )
+            .unwrap();
+        repository
+            .find_branch("main", BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+
+        assert_eq!(
+            default_base_branches(&repository),
+            ["trunk", "origin/trunk", "main", "master"]
+        );
+        let (name, _branch) = base_branch(&repository, &[]).unwrap();
+        assert_eq!(name, "origin/trunk");
+    }
+
+    #[test]
+    fn spec_in_a_subdirectory() {
+        let (_directory, repository) = init();
+        fs::create_dir(path(&repository, "source")).unwrap();
+        stage(&repository, "source/index.bs", SPEC);
+        commit(&repository);
+        checkout_branch(&repository, "some-feature");
+        stage(&repository, "source/index.bs", &SPEC.replace("Two", "2"));
+        commit(&repository);
+
+        let spec = path(&repository, "source/index.bs");
+        assert_eq!(repository_path(&spec).as_deref(), Some("source/index.bs"));
+        let diff = git_diff(&spec, &[]).unwrap();
+        assert!(diff.contains("+++ b/source/index.bs\n"));
+        assert!(diff.contains("+<p>2</p>"));
+    }
+
+    #[test]
+    fn linked_worktree_of_a_bare_clone() {
+        let (_directory, origin) = init();
+        origin.set_head("refs/heads/trunk").unwrap();
+        stage(&origin, "index.bs", SPEC);
+        commit(&origin);
+        let clones = tempfile::tempdir().unwrap();
+        let bare = git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(
+                origin.workdir().unwrap().to_str().unwrap(),
+                &clones.path().join("spec.git"),
+            )
+            .unwrap();
+        // Unlike libgit2, `git clone --bare` creates no remote-tracking branches.
+        for reference in bare.references_glob("refs/remotes/*").unwrap() {
+            reference.unwrap().delete().unwrap();
+        }
+        bare.worktree("some-feature", &clones.path().join("some-feature"), None)
+            .unwrap();
+        let worktree = Repository::open(clones.path().join("some-feature")).unwrap();
+        stage(&worktree, "index.bs", &SPEC.replace("Two", "2"));
+        commit(&worktree);
+
+        assert_eq!(
+            default_base_branches(&worktree),
+            ["trunk", "main", "master"]
+        );
+        let spec = path(&worktree, "index.bs");
+        let diff = git_diff(&spec, &[]).unwrap();
+        assert!(diff.contains("-<p>Two</p>\n+<p>2</p>\n"));
+        assert_eq!(on_base_branch(&spec, &[]), None);
+    }
+}
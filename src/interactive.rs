@@ -0,0 +1,82 @@
+use specfmt_core::diff;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+
+// Asks which of the changes formatting would make to a spec to apply, one hunk
+// at a time, like `git add -p` does. This lets you keep a few deliberately
+// formatted paragraphs untouched when formatting a large change.
+
+const HELP: &str = "\
+y - apply this change
+n - skip this change
+a - apply this change and all later ones in this spec
+d - skip this change and all later ones in this spec
+q - quit; skip this change and all later ones
+? - print help";
+
+// The hunks picked by `select()`.
+pub struct Selection {
+    // Whether to apply each hunk, in order.
+    pub selected: Vec<bool>,
+    // Whether the user quit, which skips the hunks of any later specs too.
+    pub quit: bool,
+}
+
+// Prints each of `hunks` of the spec at `path` to `out`, and reads whether to
+// apply it from `input`. Running out of input skips the remaining hunks, as if
+// the user quit.
+pub fn select(
+    path: &str,
+    hunks: &[diff::Hunk],
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+) -> io::Result<Selection> {
+    let mut selection = Selection {
+        selected: Vec::with_capacity(hunks.len()),
+        quit: false,
+    };
+    writeln!(out, "--- a/{}\n+++ b/{}", path, path)?;
+    for (i, hunk) in hunks.iter().enumerate() {
+        writeln!(out, "{}", hunk.header())?;
+        for line in &hunk.lines {
+            writeln!(out, "{}", line)?;
+        }
+
+        loop {
+            write!(
+                out,
+                "({}/{}) Apply this change [y,n,a,d,q,?]? ",
+                i + 1,
+                hunks.len()
+            )?;
+            out.flush()?;
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                writeln!(out)?;
+                selection.quit = true;
+                break;
+            }
+            match answer.trim() {
+                "y" => selection.selected.push(true),
+                "n" => selection.selected.push(false),
+                "a" | "d" => {
+                    let apply = answer.trim() == "a";
+                    selection.selected.resize(hunks.len(), apply);
+                    return Ok(selection);
+                }
+                "q" => selection.quit = true,
+                _ => {
+                    writeln!(out, "{}", HELP)?;
+                    continue;
+                }
+            }
+            break;
+        }
+        if selection.quit {
+            break;
+        }
+    }
+    selection.selected.resize(hunks.len(), false);
+    Ok(selection)
+}
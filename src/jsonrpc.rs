@@ -0,0 +1,342 @@
+use super::diagnostics::escape_json;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+
+// Just enough of JSON and JSON-RPC 2.0 for the servers that editors and other
+// tools talk to, without pulling in a JSON library. Messages are framed like
+// HTTP bodies, with a `Content-Length` header, as the Language Server Protocol
+// specifies.
+
+// The error codes defined by JSON-RPC and the Language Server Protocol.
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const REQUEST_FAILED: i64 = -32803;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // Objects keep their members in order, so that our output is stable.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object(members: Vec<(&str, Value)>) -> Value {
+        Value::Object(
+            members
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    // Returns the member `name` of an object, or `Value::Null` if there is no
+    // such member (or this isn't an object), so that lookups can be chained.
+    pub fn get(&self, name: &str) -> &Value {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(member, _)| member == name)
+                .map_or(&Value::Null, |(_, value)| value),
+            _ => &Value::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Value::Number(number) if *number >= 0.0 && number.fract() == 0.0 => {
+                Some(*number as usize)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Value {
+        Value::String(string.to_string())
+    }
+}
+
+impl From<usize> for Value {
+    fn from(number: usize) -> Value {
+        Value::Number(number as f64)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Boolean(boolean) => write!(f, "{}", boolean),
+            // Integers are written without a fractional part, like JavaScript
+            // does.
+            Value::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => {
+                write!(f, "{}", *number as i64)
+            }
+            Value::Number(number) => write!(f, "{}", number),
+            Value::String(string) => write!(f, "\"{}\"", escape_json(string)),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "\"{}\":{}", escape_json(name), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += c.len_utf8();
+                Ok(())
+            }
+            _ => Err(format!(
+                "expected '{}' at offset {}",
+                expected, self.position
+            )),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        let rest = &self.text[self.position..];
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.position += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let name = self.parse_string()?;
+                    self.expect(':')?;
+                    members.push((name, self.parse_value()?));
+                    self.skip_whitespace();
+                    if self.peek() == Some('}') {
+                        self.position += 1;
+                        return Ok(Value::Object(members));
+                    }
+                    self.expect(',')?;
+                }
+            }
+            Some('[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.parse_value()?);
+                    self.skip_whitespace();
+                    if self.peek() == Some(']') {
+                        self.position += 1;
+                        return Ok(Value::Array(values));
+                    }
+                    self.expect(',')?;
+                }
+            }
+            Some('"') => Ok(Value::String(self.parse_string()?)),
+            _ => {
+                for (literal, value) in [
+                    ("null", Value::Null),
+                    ("true", Value::Boolean(true)),
+                    ("false", Value::Boolean(false)),
+                ] {
+                    if rest.starts_with(literal) {
+                        self.position += literal.len();
+                        return Ok(value);
+                    }
+                }
+                let end = rest
+                    .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+                    .unwrap_or(rest.len());
+                match rest[..end].parse() {
+                    Ok(number) if end > 0 => {
+                        self.position += end;
+                        Ok(Value::Number(number))
+                    }
+                    _ => Err(format!("unexpected value at offset {}", self.position)),
+                }
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        let mut chars = self.text[self.position..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.position += i + 1;
+                    return Ok(string);
+                }
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\' | '/'))) => string.push(c),
+                    Some((_, 'b')) => string.push('\u{8}'),
+                    Some((_, 'f')) => string.push('\u{c}'),
+                    Some((_, 'n')) => string.push('\n'),
+                    Some((_, 'r')) => string.push('\r'),
+                    Some((_, 't')) => string.push('\t'),
+                    Some((_, 'u')) => {
+                        let high = code_unit(&mut chars)?;
+                        // Characters outside the Basic Multilingual Plane are
+                        // escaped as a surrogate pair.
+                        let units = if (0xD800..0xDC00).contains(&high) {
+                            match (chars.next(), chars.next()) {
+                                (Some((_, '\\')), Some((_, 'u'))) => {
+                                    vec![high, code_unit(&mut chars)?]
+                                }
+                                _ => return Err(String::from("unpaired surrogate")),
+                            }
+                        } else {
+                            vec![high]
+                        };
+                        string.push_str(
+                            &String::from_utf16(&units)
+                                .map_err(|_| String::from("unpaired surrogate"))?,
+                        );
+                    }
+                    _ => return Err(String::from("invalid escape sequence")),
+                },
+                c => string.push(c),
+            }
+        }
+        Err(String::from("unterminated string"))
+    }
+}
+
+// Reads the 4 hex digits of a `\u` escape from `chars`.
+fn code_unit(chars: &mut std::str::CharIndices) -> Result<u16, String> {
+    let hex: String = (0..4)
+        .filter_map(|_| chars.next())
+        .map(|(_, c)| c)
+        .collect();
+    u16::from_str_radix(&hex, 16).map_err(|_| String::from("invalid \\u escape"))
+}
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, position: 0 };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.position != text.len() {
+        return Err(format!("unexpected text at offset {}", parser.position));
+    }
+    Ok(value)
+}
+
+// Reads the next message from `reader`, or returns `None` at the end of the
+// input.
+pub fn read_message(reader: &mut dyn BufRead) -> io::Result<Option<String>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        if header.trim().is_empty() {
+            // Tolerate blank lines between messages.
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn write_message(writer: &mut dyn Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+pub fn response(id: Value, result: Value) -> Value {
+    Value::object(vec![
+        ("jsonrpc", Value::from("2.0")),
+        ("id", id),
+        ("result", result),
+    ])
+}
+
+pub fn error_response(id: Value, code: i64, message: &str) -> Value {
+    Value::object(vec![
+        ("jsonrpc", Value::from("2.0")),
+        ("id", id),
+        (
+            "error",
+            Value::object(vec![
+                ("code", Value::Number(code as f64)),
+                ("message", Value::from(message)),
+            ]),
+        ),
+    ])
+}
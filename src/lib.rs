@@ -0,0 +1,5 @@
+// The formatting core of specfmt lives in the `specfmt-core` crate, which tools
+// that embed the formatter should depend on. This re-exports it for those that
+// depend on `specfmt` with `default-features = false`.
+
+pub use specfmt_core::*;
@@ -0,0 +1,334 @@
+use super::config;
+use super::jsonrpc;
+use super::jsonrpc::Value;
+use super::FormatOptions;
+use super::Range;
+use clap::ArgMatches;
+use std::collections::HashMap;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::PathBuf;
+
+// A Language Server Protocol server over stdio, so editors can format specs on
+// save. It implements `textDocument/formatting` and
+// `textDocument/rangeFormatting` for the documents the editor has open, which
+// it syncs in full on every change. See
+// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/.
+//
+// Documents are formatted in memory, scoped exactly as `--stdin-filepath` would
+// scope them: to the changes of the file on disk, unless formatting the entire
+// spec (`--full-spec`) or a range.
+
+// Returns the path of a `file:` `uri`, like `file:///home/me/spec%20draft.bs`.
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // Skip the host, which is usually empty.
+    let path = &path[path.find('/')?..];
+
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let escaped = match (byte, after) {
+            (b'%', [high, low, ..]) => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &after[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn position(line: usize, character: usize) -> Value {
+    Value::object(vec![
+        ("line", Value::from(line)),
+        ("character", Value::from(character)),
+    ])
+}
+
+// Returns the edits that turn `old` into `new`: a single edit replacing the
+// lines between their common prefix and suffix, so that editors keep the
+// cursor and folds of the untouched parts of the document.
+fn text_edits(old: &str, new: &str) -> Vec<Value> {
+    if old == new {
+        return Vec::new();
+    }
+    let old_lines: Vec<&str> = old.split('\n').collect();
+    let new_lines: Vec<&str> = new.split('\n').collect();
+    // Leave at least one line of the shorter document to replace, so that a
+    // document that only gains or loses lines at its end gets a valid range.
+    let shorter = usize::min(old_lines.len(), new_lines.len());
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(old, new)| old == new)
+        .count()
+        .min(shorter - 1);
+    let max_suffix = shorter - prefix;
+    let suffix = old_lines
+        .iter()
+        .rev()
+        .zip(new_lines.iter().rev())
+        .take(max_suffix)
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let replaced = &new_lines[prefix..new_lines.len() - suffix];
+    let (end, text) = if suffix > 0 {
+        // Replace whole lines, up to the start of the first line of the
+        // common suffix.
+        let text: String = replaced.iter().map(|line| format!("{}\n", line)).collect();
+        (position(old_lines.len() - suffix, 0), text)
+    } else {
+        // Replace through the end of the document. Positions count UTF-16
+        // code units, like JavaScript strings do.
+        let last_line = old_lines[old_lines.len() - 1];
+        (
+            position(old_lines.len() - 1, last_line.encode_utf16().count()),
+            replaced.join("\n"),
+        )
+    };
+    vec![Value::object(vec![
+        (
+            "range",
+            Value::object(vec![("start", position(prefix, 0)), ("end", end)]),
+        ),
+        ("newText", Value::String(text)),
+    ])]
+}
+
+pub struct Server<'a> {
+    options: &'a FormatOptions,
+    matches: &'a ArgMatches,
+    // The contents of the open documents, by URI.
+    documents: HashMap<String, String>,
+    shutdown: bool,
+}
+
+impl<'a> Server<'a> {
+    pub fn new(options: &'a FormatOptions, matches: &'a ArgMatches) -> Server<'a> {
+        Server {
+            options,
+            matches,
+            documents: HashMap::new(),
+            shutdown: false,
+        }
+    }
+
+    // Formats the open document `uri`, or just the lines `range` (0-based,
+    // inclusive) of it, returning the edits to make.
+    fn format(&self, uri: &str, range: Option<(usize, usize)>) -> Result<Value, String> {
+        let contents = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| format!("'{}' is not open", uri))?;
+        let path = path_from_uri(uri).ok_or_else(|| format!("'{}' is not a file: URI", uri))?;
+
+        let mut options =
+            config::apply(self.options, self.matches, &path).map_err(|error| error.to_string())?;
+        if let Some((start, end)) = range {
+            options.full_spec = false;
+            options.staged = false;
+            options.range = Some(Range::Lines(start + 1, end + 1));
+            options.range_bytes = None;
+            options.lines = None;
+            options.section = None;
+        }
+        super::unscope_outside_git(&mut options, &path);
+        super::scope_on_base_branch(&mut options, &path);
+        let scoped = !options.full_spec && !options.has_range();
+        if scoped && !path.is_file() {
+            return Err(format!(
+                "Cannot scope formatting to the changes to '{}', which does not exist. Configure full-spec, or format a range.",
+                path.display()
+            ));
+        }
+
+        let diff = super::try_scoped_diff(&path, &options).map_err(|error| error.to_string())?;
+        let diff = super::sanitized_diff_lines(&diff, options.word_diff);
+        let rewrapped = super::format_spec(contents.clone(), &diff, &options)
+            .map_err(|error| error.to_string())?;
+        Ok(Value::Array(text_edits(
+            contents,
+            &rewrapped.lines.join("\n"),
+        )))
+    }
+
+    // Handles the request or notification `message`, returning the response
+    // to send, if any.
+    fn handle(&mut self, message: &Value) -> Option<Value> {
+        let id = message.get("id").clone();
+        let params = message.get("params");
+        let uri = params.get("textDocument").get("uri").as_str();
+        let method = match message.get("method").as_str() {
+            Some(method) => method,
+            None => {
+                return Some(jsonrpc::error_response(
+                    id,
+                    jsonrpc::INVALID_REQUEST,
+                    "Missing method",
+                ))
+            }
+        };
+        if self.shutdown {
+            return (id != Value::Null).then(|| {
+                jsonrpc::error_response(id, jsonrpc::INVALID_REQUEST, "The server is shut down")
+            });
+        }
+
+        let result = match (method, uri) {
+            ("initialize", _) => Ok(Value::object(vec![
+                (
+                    "capabilities",
+                    Value::object(vec![
+                        // Full document sync.
+                        ("textDocumentSync", Value::from(1)),
+                        ("documentFormattingProvider", Value::Boolean(true)),
+                        ("documentRangeFormattingProvider", Value::Boolean(true)),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Value::object(vec![
+                        ("name", Value::from("specfmt")),
+                        ("version", Value::from(env!("CARGO_PKG_VERSION"))),
+                    ]),
+                ),
+            ])),
+            ("shutdown", _) => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            ("textDocument/didOpen", Some(uri)) => {
+                if let Some(text) = params.get("textDocument").get("text").as_str() {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                return None;
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                let changes = params.get("contentChanges").as_array().unwrap_or_default();
+                if let Some(text) = changes
+                    .last()
+                    .and_then(|change| change.get("text").as_str())
+                {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                return None;
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                return None;
+            }
+            ("textDocument/formatting", Some(uri)) => self.format(uri, None),
+            ("textDocument/rangeFormatting", Some(uri)) => {
+                let range = params.get("range");
+                let start = range.get("start").get("line").as_usize();
+                let end = range.get("end");
+                match (start, end.get("line").as_usize()) {
+                    (Some(start), Some(end_line)) => {
+                        // A range ending at the start of a line doesn't
+                        // include that line.
+                        let end_line = if end.get("character").as_usize() == Some(0) {
+                            usize::max(end_line.saturating_sub(1), start)
+                        } else {
+                            end_line
+                        };
+                        self.format(uri, Some((start, usize::max(start, end_line))))
+                    }
+                    _ => {
+                        return Some(jsonrpc::error_response(
+                            id,
+                            jsonrpc::INVALID_PARAMS,
+                            "Missing range",
+                        ))
+                    }
+                }
+            }
+            ("textDocument/formatting" | "textDocument/rangeFormatting", None) => {
+                return Some(jsonrpc::error_response(
+                    id,
+                    jsonrpc::INVALID_PARAMS,
+                    "Missing textDocument",
+                ))
+            }
+            _ => {
+                // Notifications we don't know, like `initialized`, are ignored.
+                return (id != Value::Null).then(|| {
+                    jsonrpc::error_response(
+                        id,
+                        jsonrpc::METHOD_NOT_FOUND,
+                        &format!("Unsupported method '{}'", method),
+                    )
+                });
+            }
+        };
+        Some(match result {
+            Ok(result) => jsonrpc::response(id, result),
+            Err(message) => jsonrpc::error_response(id, jsonrpc::REQUEST_FAILED, &message),
+        })
+    }
+
+    // Serves the messages read from `input` until the client asks us to exit,
+    // writing responses to `output`. Returns the exit code, which is 1 if the
+    // client didn't shut us down first.
+    pub fn serve(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<i32> {
+        while let Some(message) = jsonrpc::read_message(input)? {
+            let message = match jsonrpc::parse(&message) {
+                Ok(message) => message,
+                Err(error) => {
+                    jsonrpc::write_message(
+                        output,
+                        &jsonrpc::error_response(Value::Null, jsonrpc::PARSE_ERROR, &error),
+                    )?;
+                    continue;
+                }
+            };
+            if message.get("method").as_str() == Some("exit") {
+                return Ok(if self.shutdown { 0 } else { 1 });
+            }
+            if let Some(response) = self.handle(&message) {
+                jsonrpc::write_message(output, &response)?;
+            }
+        }
+        Ok(1)
+    }
+}
+
+// Serves editors over stdio until they ask us to exit.
+pub fn run(options: &FormatOptions, matches: &ArgMatches) -> io::Result<i32> {
+    Server::new(options, matches).serve(&mut io::stdin().lock(), &mut io::stdout().lock())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Returns the only edit that turns `old` into `new`, as JSON.
+    fn edit(old: &str, new: &str) -> String {
+        let edits = text_edits(old, new);
+        assert_eq!(edits.len(), 1);
+        edits[0].to_string()
+    }
+
+    #[test]
+    fn trailing_lines_added_or_removed() {
+        assert_eq!(
+            edit("a\nb", "a\nb\n"),
+            r#"{"range":{"start":{"line":1,"character":0},"end":{"line":1,"character":1}},"newText":"b\n"}"#
+        );
+        assert_eq!(
+            edit("a\nb\nc", "a\nb"),
+            r#"{"range":{"start":{"line":1,"character":0},"end":{"line":2,"character":1}},"newText":"b"}"#
+        );
+    }
+}
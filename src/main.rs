@@ -1,12 +1,13 @@
+use clap::ArgMatches;
 use clap::CommandFactory;
+use clap::FromArgMatches;
 use clap::Parser;
-use std::fs::read_dir;
+use clap::Subcommand;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io;
+use std::io::IsTerminal;
 use std::io::Read;
-use std::io::Seek;
-use std::io::SeekFrom;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
@@ -14,17 +15,30 @@ use std::path::PathBuf;
 // Adapted from the web version of the original rewrapper
 // (https://github.com/domenic/rewrapper).
 
-mod rewrapper;
+mod annotations;
+mod config;
+mod daemon;
+mod diagnostics;
+mod discovery;
+mod error;
+#[cfg(feature = "git")]
+mod git;
+mod interactive;
+mod jsonrpc;
+mod lsp;
+mod metadata;
+mod preview;
+mod report;
 
-// A simple struct that we use to track each line of the source specification.
-// When scoping our reformatting changes to lines in a `git diff`, lines in the
-// spec do not also appear in the diff will have `should_format = false`. We
-// dynamically make other lines exempt from formatting based on other exceptions
-// and rules as well.
-pub struct Line<'a> {
-    should_format: bool,
-    contents: &'a str,
-}
+use specfmt_core::diff;
+use specfmt_core::json;
+use specfmt_core::rewrapper;
+use specfmt_core::sections;
+use specfmt_core::Line;
+
+use diagnostics::Diagnostic;
+use diagnostics::ErrorFormat;
+use error::SpecfmtError;
 
 fn read_file(filename: &Path) -> Result<(File, String), io::Error> {
     let mut file = OpenOptions::new()
@@ -37,37 +51,839 @@ fn read_file(filename: &Path) -> Result<(File, String), io::Error> {
     Ok((file, contents))
 }
 
-fn write_file(mut file: File, contents: String) -> Result<u8, io::Error> {
-    // Will always work because `file` is opened for writing.
-    file.set_len(0)?;
-    file.seek(SeekFrom::Start(0))?;
-    file.write_all(contents.as_bytes())?;
+// Writes `contents` to `filename` by writing them to a temporary file next to
+// it, and renaming that over it. Renaming is atomic, so if we crash or the disk
+// fills up midway, the spec is left as it was rather than truncated. Anything
+// other than a regular file (like `/dev/null` or a pipe) is written to directly,
+// since renaming over it would replace it.
+fn write_file(filename: &Path, contents: String) -> Result<u8, io::Error> {
+    // Replace the file a symlink points to, not the symlink.
+    let filename = match std::fs::canonicalize(filename) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => filename.to_path_buf(),
+        result => result?,
+    };
+    if std::fs::metadata(&filename).is_ok_and(|metadata| !metadata.is_file()) {
+        std::fs::write(&filename, contents)?;
+        return Ok(0);
+    }
+    let mut temporary = filename.as_os_str().to_owned();
+    temporary.push(format!(".specfmt-{}.tmp", std::process::id()));
+    let temporary = PathBuf::from(temporary);
+
+    let write = || -> Result<(), io::Error> {
+        let mut file = File::create(&temporary)?;
+        file.write_all(contents.as_bytes())?;
+        if let Ok(metadata) = std::fs::metadata(&filename) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&temporary, &filename)
+    };
+    if let Err(error) = write() {
+        let _ = std::fs::remove_file(&temporary);
+        return Err(error);
+    }
     Ok(0)
 }
 
 /// Formats Bikeshed and Wattsi specifications using WHATWG conventions.
 #[derive(Parser, Debug)]
-#[command(version)]
+#[command(version, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// The specification to reformat. Defaults to "source" or the unique .bs
-    /// file in the current directory.
-    filename: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Number of columns to wrap to.
-    #[arg(long, default_value_t = 100)]
-    wrap: u8,
+    #[command(flatten)]
+    specs: SpecFiles,
 
     /// Force-reformat the spec even if it has uncommitted changes.
     #[arg(short, long, default_value_t = false)]
     force: bool,
 
+    /// Check that the spec is formatted without writing it, reporting the lines
+    /// that formatting would change. Exits with the same codes as `specfmt
+    /// ci`.
+    #[arg(long, default_value_t = false, conflicts_with = "stdin_filepath")]
+    check: bool,
+
+    /// Print a unified diff of the changes that formatting would make to
+    /// stdout, instead of writing them to the spec.
+    #[arg(long, visible_alias = "diff", default_value_t = false,
+          conflicts_with_all = ["check", "stdin_filepath", "report"])]
+    dry_run: bool,
+
+    /// When to color the diff printed by `--dry-run`: "auto" (when printing to
+    /// a terminal, and NO_COLOR isn't set), "always" (e.g., to pipe it into
+    /// `less -R`), or "never".
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    color: ColorWhen,
+
+    /// Check that the spec is formatted like `--check` does, and write the
+    /// changes that formatting would make to FILE as a patch that `git apply`
+    /// (run from the root of the repository) can apply.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["dry_run", "stdin_filepath"])]
+    patch: Option<PathBuf>,
+
+    /// Show each change that formatting would make, and ask whether to apply
+    /// it before writing the spec, like `git add -p` does. The answers are
+    /// read from stdin, which `--files-from` may read the specs from instead.
+    #[arg(short, long, default_value_t = false,
+          conflicts_with_all = ["check", "dry_run", "patch", "stdin_filepath", "files_from"])]
+    interactive: bool,
+
+    /// Write the formatted spec to FILE instead of overwriting the spec, or to
+    /// stdout if FILE is "-".
+    #[arg(short, long, value_name = "FILE",
+          conflicts_with_all = ["check", "dry_run", "patch", "stdin_filepath", "files_from",
+                                "recursive", "staged"])]
+    output: Option<PathBuf>,
+
+    /// Save the contents of each spec to FILENAME.specfmt.orig before formatting
+    /// it, so that `--revert` can restore them.
+    #[arg(long, default_value_t = false,
+          conflicts_with_all = ["dry_run", "output", "stdin_filepath"])]
+    backup: bool,
+
+    /// Restore each spec from the FILENAME.specfmt.orig backup that `--backup`
+    /// saved, and delete the backup.
+    #[arg(long, default_value_t = false,
+          conflicts_with_all = ["check", "dry_run", "patch", "interactive", "output", "backup",
+                                "stdin_filepath", "fail_on_change"])]
+    revert: bool,
+
+    /// Exit with code 1 if formatting changed any spec (or would have, in a dry
+    /// run), so scripts can tell that something was reformatted.
+    #[arg(long, default_value_t = false, conflicts_with = "check")]
+    fail_on_change: bool,
+
+    /// List the files considered when looking for the spec in the directory
+    /// FILENAME (or the current one), and why each was or wasn't chosen.
+    #[arg(long, default_value_t = false)]
+    list_candidates: bool,
+
+    /// Read the spec from stdin and write the formatted spec to stdout, for
+    /// editor integrations. PATH is where the spec lives, which is used to
+    /// scope formatting to its changes just like for the file itself.
+    #[arg(long, value_name = "PATH",
+          conflicts_with_all = ["filename", "files_from", "staged", "report"])]
+    stdin_filepath: Option<PathBuf>,
+
+    /// Run a Language Server Protocol server over stdio, which formats the
+    /// documents open in an editor on `textDocument/formatting` and
+    /// `textDocument/rangeFormatting` requests.
+    #[arg(long, default_value_t = false,
+          conflicts_with_all = ["filename", "files_from", "recursive", "check", "dry_run",
+                                "list_candidates", "stdin_filepath", "range", "range_bytes",
+                                "lines", "section", "report"])]
+    lsp: bool,
+
+    #[command(flatten)]
+    format: FormatOptions,
+}
+
+// The specifications to operate on, shared by all modes of operation.
+#[derive(clap::Args, Debug)]
+struct SpecFiles {
+    /// The specification to format. Defaults to "source" or the unique .bs
+    /// file in the current directory. Use "-" to format the entire spec read
+    /// from stdin, writing the result to stdout.
+    #[arg(conflicts_with = "files_from")]
+    filename: Option<String>,
+
+    /// Read the specifications to format from FILE, one per line or
+    /// NUL-separated. Use "-" to read from stdin, e.g., `git diff --name-only
+    /// -z | specfmt --files-from -`. Listed files that don't exist are skipped.
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// Format every spec in the directory FILENAME (or the current one) and
+    /// its subdirectories, i.e., every file matching any of the spec patterns.
+    #[arg(short, long, default_value_t = false, conflicts_with = "files_from")]
+    recursive: bool,
+
+    #[command(flatten)]
+    discovery: DiscoveryOptions,
+}
+
+#[derive(clap::Args, Debug)]
+struct DiscoveryOptions {
+    /// Comma-separated list of patterns for finding the spec in a directory,
+    /// in order of preference. `*` matches any characters, and `?` any one.
+    #[arg(long, value_delimiter = ',', value_name = "PATTERNS",
+          default_value = discovery::DEFAULT_PATTERNS)]
+    spec_pattern: Vec<String>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorWhen {
+    fn enabled(self) -> bool {
+        match self {
+            ColorWhen::Auto => io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum OnBaseBranch {
+    Suggest,
+    LastCommit,
+    WorkingTree,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum LineEnding {
+    Auto,
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    // Whether the lines of `contents` should end in CRLF.
+    fn is_crlf(self, contents: &str) -> bool {
+        match self {
+            LineEnding::Auto => {
+                contents.matches("\r\n").count() * 2 > contents.matches('\n').count()
+            }
+            LineEnding::Lf => false,
+            LineEnding::Crlf => true,
+        }
+    }
+}
+
+// Options that control how a spec is formatted, shared by all modes of
+// operation.
+#[derive(clap::Args, Clone, Debug)]
+struct FormatOptions {
+    /// Number of columns (at least 20) to wrap to, or 0 to join paragraphs
+    /// into single lines without ever splitting them, or "auto" to detect
+    /// whether the spec is wrapped to 80 or 100 columns (falling back to 100).
+    /// Defaults to the width of a `<!-- specfmt: wrap=COLUMNS -->` comment in
+    /// the spec, if any, or else to its Bikeshed metadata
+    /// (`Specfmt: wrap=COLUMNS` or `Line Length: COLUMNS`), if any, or else
+    /// 100.
+    #[arg(long, value_name = "COLUMNS", value_parser = parse_wrap)]
+    wrap: Option<Wrap>,
+
     /// Reformat the entire spec, not scoped to the changes of the current branch.
     #[arg(long, default_value_t = false)]
     full_spec: bool,
+
+    /// Comma-separated list of branches (e.g., "upstream/main,main") to compare
+    /// the current branch against, in order of preference. The first one that
+    /// exists is used. Defaults to the upstream branch of the current branch
+    /// (unless it's the same-named branch on a remote), the default branch of
+    /// the "origin" remote, then "main" and "master".
+    #[arg(long, value_delimiter = ',', value_name = "BRANCHES")]
+    base_branch: Vec<String>,
+
+    /// Scope formatting to the changes since COMMIT, which can be any commit
+    /// (e.g., a SHA, a tag, or "HEAD~3"), instead of those since a base branch.
+    #[arg(long, value_name = "COMMIT", conflicts_with_all = ["full_spec", "staged"])]
+    base_commit: Option<String>,
+
+    /// Scope formatting to the uncommitted changes to the spec, i.e., what you
+    /// just typed. Since those are what's formatted, the spec may have
+    /// uncommitted changes without `--force`.
+    #[arg(long, default_value_t = false,
+          conflicts_with_all = ["full_spec", "staged", "base_commit"])]
+    working_tree: bool,
+
+    /// Don't rewrap the paragraphs of changes that only edit words in place
+    /// (e.g., fixing a typo), like `git diff --word-diff` shows them, only
+    /// those of changes to the whitespace or structure of lines.
+    #[arg(long, default_value_t = false,
+          conflicts_with_all = ["full_spec", "range", "range_bytes", "lines", "section"])]
+    word_diff: bool,
+
+    /// How far formatting reaches beyond the changed lines: just the "line"s
+    /// themselves, their whole "paragraph"s, or their whole "block"s up to the
+    /// nearest blank lines. By default, a changed line is joined onto the line
+    /// before it.
+    #[arg(long, value_enum, value_name = "SCOPE", conflicts_with = "full_spec")]
+    scope: Option<rewrapper::Scope>,
+
+    /// What to format when the current branch is the base branch (e.g., when
+    /// on "main" itself), which has no changes of its own: nothing, but
+    /// "suggest" how to format it instead, the changes of the "last-commit",
+    /// or the uncommitted changes in the "working-tree".
+    #[arg(long, value_enum, value_name = "BEHAVIOR", default_value_t = OnBaseBranch::Suggest)]
+    on_base_branch: OnBaseBranch,
+
+    /// Scope formatting to the changes staged in the index, for use in a
+    /// pre-commit hook. The formatting changes are staged as well.
+    #[arg(long, default_value_t = false, conflicts_with = "full_spec")]
+    staged: bool,
+
+    /// With `--staged`, leave the formatting changes unstaged, e.g., for
+    /// hooks that fail the commit so the changes can be reviewed first.
+    #[arg(long, default_value_t = false, requires = "staged")]
+    no_restage: bool,
+
+    /// Scope formatting to the paragraphs enclosing lines START to END
+    /// (1-based, inclusive), for use by editors.
+    #[arg(long, value_name = "START:END", value_parser = parse_line_range,
+          conflicts_with_all = ["full_spec", "staged", "range_bytes"])]
+    range: Option<Range>,
+
+    /// Like `--range`, but given as byte offsets (0-based, END exclusive).
+    #[arg(long, value_name = "START..END", value_parser = parse_byte_range,
+          conflicts_with_all = ["full_spec", "staged"])]
+    range_bytes: Option<Range>,
+
+    /// Scope formatting to exactly lines START to END (1-based, inclusive),
+    /// without reaching into the rest of their paragraphs, e.g., to reformat
+    /// one section of an old spec at a time.
+    #[arg(long, value_name = "START:END", value_parser = parse_line_range,
+          conflicts_with_all = ["full_spec", "staged", "range", "range_bytes"])]
+    lines: Option<Range>,
+
+    /// Scope formatting to the section whose heading has the text (e.g.,
+    /// "Fetch API") or id TITLE, up to the next heading of the same or a higher
+    /// level, e.g., to adopt specfmt in a large spec one section at a time.
+    #[arg(long, value_name = "TITLE",
+          conflicts_with_all = ["full_spec", "staged", "range", "range_bytes", "lines"])]
+    section: Option<String>,
+
+    /// Pretty-print JSON examples in `<pre class="highlight json">` blocks and
+    /// ```json fences with 2-space indentation, if they are among the lines
+    /// being formatted.
+    #[arg(long, default_value_t = false)]
+    format_json: bool,
+
+    /// Break overlong tags between their attributes, aligning continuation
+    /// lines under the first attribute.
+    #[arg(long, default_value_t = false)]
+    wrap_attributes: bool,
+
+    /// Comma-separated list of tags (e.g., "a,dfn,div") that keep the line
+    /// break before them instead of being joined onto the previous line.
+    #[arg(long, value_delimiter = ',', value_name = "TAGS")]
+    preserve_breaks_before: Vec<String>,
+
+    /// Comma-separated list of tags (e.g., "css-example,x-grammar") of custom
+    /// elements whose content is exempt from formatting, like that of `<pre>`.
+    #[arg(long, value_delimiter = ',', value_name = "TAGS")]
+    exempt_tags: Vec<String>,
+
+    /// Comma-separated list of tags (e.g., "span,code,a") of inline elements
+    /// to keep on one line, from their open tag through their end tag, whenever
+    /// they fit on one.
+    #[arg(long, value_delimiter = ',', value_name = "TAGS")]
+    atomic_elements: Vec<String>,
+
+    /// Put paragraph text on the same line as its `<p>` tag (the WHATWG
+    /// convention), or on the line after it.
+    #[arg(long, value_enum, value_name = "PLACEMENT")]
+    paragraph_content: Option<rewrapper::ParagraphContent>,
+
+    /// How far to indent the continuation lines of a wrapped line beyond its
+    /// first line: "marker" aligns them with the text after a leading list
+    /// marker (like `1. `, `1) `, or `* `) or definition marker (`: ` or
+    /// `:: `), "element" also aligns them with the text after a leading
+    /// `<li>`, `<dt>`, or `<dd>` tag, and a number indents them by that many
+    /// spaces.
+    #[arg(long, value_name = "INDENT", default_value = "marker")]
+    continuation_indent: rewrapper::ContinuationIndent,
+
+    /// Comma-separated list of rules (e.g., "li=align,::=4") for indenting the
+    /// continuation lines of lines that start with particular markers, which
+    /// override `--continuation-indent` for them. Each rule names a marker
+    /// (`1.`, `*`, `:`, `::`, `li`, `dt`, or `dd`) and how far to indent:
+    /// "align" to align with the text after it, or a number of spaces.
+    #[arg(long, value_delimiter = ',', value_name = "RULES")]
+    continuation_rules: Vec<rewrapper::ContinuationRule>,
+
+    /// How to choose where to break lines: "greedy" fits as many words as
+    /// possible on each line, so that a change only rewraps its paragraph from
+    /// the changed line on, and "optimal" evens out the line lengths of the
+    /// whole paragraph, so that a change may rewrap all of it.
+    #[arg(long, value_enum, value_name = "ALGORITHM", default_value = "greedy")]
+    wrap_algorithm: rewrapper::WrapAlgorithm,
+
+    /// Keep the line break after every line that ends a sentence, for specs
+    /// written with one sentence per line. Lines that are too long are still
+    /// wrapped.
+    #[arg(long, default_value_t = false)]
+    respect_sentence_breaks: bool,
+
+    /// Never join lines, and only split the ones that are too long, for the
+    /// smallest possible changes.
+    #[arg(long, default_value_t = false)]
+    no_unwrap: bool,
+
+    /// Treat the spec as plain HTML, rather than as Bikeshed markdown whose
+    /// code fences, tables, headings, list items, and blockquotes are kept
+    /// apart from the prose around them. Defaults to what the spec's
+    /// `Markup Shorthands` metadata says about markdown, if anything.
+    #[arg(long, default_value_t = false)]
+    no_markdown: bool,
+
+    /// Break lines between Chinese and Japanese characters (except where
+    /// kinsoku rules forbid it), rather than only at spaces, so that long lines
+    /// of such text fit too.
+    #[arg(long, default_value_t = false)]
+    break_cjk: bool,
+
+    /// Measure HTML character references, like `&amp;` or `&#x2014;`, as the
+    /// width of the character they render as, rather than of their source.
+    #[arg(long, default_value_t = false)]
+    rendered_entity_width: bool,
+
+    /// The number of columns between tab stops, for measuring lines with tabs.
+    #[arg(long, value_name = "COLUMNS", default_value_t = rewrapper::DEFAULT_TAB_WIDTH,
+          value_parser = parse_tab_width)]
+    tab_width: usize,
+
+    /// Replace the tabs in the indentation of the lines being formatted with
+    /// spaces, up to the same tab stops, before rewrapping them.
+    #[arg(long, default_value_t = false)]
+    expand_tabs: bool,
+
+    /// The line ending to write: "auto" keeps whichever of LF and CRLF most of
+    /// the spec's lines end with, and "lf" and "crlf" convert every line to
+    /// that ending.
+    #[arg(long, value_enum, value_name = "ENDING", default_value = "auto")]
+    line_ending: LineEnding,
+
+    /// End the spec with a newline if it doesn't already, rather than keeping
+    /// it without one.
+    #[arg(long, default_value_t = false)]
+    ensure_final_newline: bool,
+
+    /// Remove the spaces and tabs at the ends of the lines being formatted,
+    /// other than those in exempt blocks like `<pre>`.
+    #[arg(long, default_value_t = false)]
+    strip_trailing_whitespace: bool,
+
+    /// Reindent the lines being formatted by how deeply they're nested in
+    /// block elements like `<div>`, `<ol>`, `<li>`, and `<dd>`, one space per
+    /// level as in the HTML Standard, instead of keeping their indentation.
+    #[arg(long, default_value_t = false)]
+    reindent: bool,
+
+    /// Regular expression matching lines that are allowed to be longer than
+    /// the wrap width, like ones containing long URLs. Such lines are not
+    /// reported as overflowing. May be given multiple times.
+    #[arg(long, value_name = "REGEX", value_parser = regex::Regex::new)]
+    allow_long_line: Vec<regex::Regex>,
+
+    /// How to report warnings and errors: "human" readable text, one "short"
+    /// line per diagnostic, one "json" object per line, "github" Actions
+    /// annotations, or a "sarif" log. Defaults to "human", or "github" for
+    /// `specfmt ci`.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        visible_alias = "output-format"
+    )]
+    error_format: Option<ErrorFormat>,
+
+    /// Print a report of the changes that formatting makes (or would make, when
+    /// checking) to stdout, as a "json" document listing the changed lines of
+    /// each spec and why they changed. Everything else goes to stderr.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    report: Option<report::ReportFormat>,
+}
+
+impl FormatOptions {
+    fn error_format(&self) -> ErrorFormat {
+        self.error_format.unwrap_or(ErrorFormat::Human)
+    }
+
+    // Whether formatting is scoped to a range of lines (with `--range`,
+    // `--range-bytes`, `--lines`, or `--section`) rather than to the changes in
+    // git.
+    fn has_range(&self) -> bool {
+        self.range.is_some()
+            || self.range_bytes.is_some()
+            || self.lines.is_some()
+            || self.section.is_some()
+    }
+
+    // Where progress messages and diagnostics go: stdout, unless it's reserved
+    // for the report.
+    fn output(&self) -> Box<dyn Write> {
+        if self.report.is_some() {
+            Box::new(io::stderr())
+        } else {
+            Box::new(io::stdout())
+        }
+    }
+}
+
+// The byte order mark that some editors start UTF-8 files with.
+const BOM: char = '\u{FEFF}';
+
+// The width to wrap to when neither `--wrap` nor the spec says otherwise.
+const DEFAULT_WRAP: usize = 100;
+
+// The width to wrap to.
+#[derive(Clone, Copy, Debug)]
+enum Wrap {
+    Columns(usize),
+    // Whatever width the spec is already wrapped to.
+    Auto,
+}
+
+fn parse_wrap(wrap: &str) -> Result<Wrap, String> {
+    if wrap == "auto" {
+        return Ok(Wrap::Auto);
+    }
+    parse_columns(wrap).map(Wrap::Columns).map_err(|_| {
+        format!(
+            "'{}' is not \"auto\", 0, or a number of columns of at least {}",
+            wrap,
+            rewrapper::MIN_COLUMN_LENGTH
+        )
+    })
+}
+
+// Parses a number of columns to wrap to, which must be 0 (for no limit) or wide
+// enough to fit more than a word or two on each line.
+fn parse_columns(columns: &str) -> Result<usize, String> {
+    let error = || {
+        format!(
+            "'{}' is not 0 or a number of columns of at least {}",
+            columns,
+            rewrapper::MIN_COLUMN_LENGTH
+        )
+    };
+    match columns.parse().map_err(|_| error())? {
+        columns @ 1.. if columns < rewrapper::MIN_COLUMN_LENGTH => Err(error()),
+        columns => Ok(columns),
+    }
+}
+
+// Parses the number of columns between tab stops, which can't be 0.
+fn parse_tab_width(columns: &str) -> Result<usize, String> {
+    match columns.parse() {
+        Ok(columns @ 1..) => Ok(columns),
+        _ => Err(format!("'{}' is not a positive number of columns", columns)),
+    }
 }
 
-fn default_filename(filename: Option<String>) -> Result<PathBuf, clap::error::Error> {
-    let mut directory = String::from(".");
+// Returns the width of the first `<!-- specfmt: wrap=COLUMNS -->` comment in
+// `contents`, which lets a spec declare its own width, if it has one.
+fn wrap_directive(contents: &str) -> Result<Option<Wrap>, SpecfmtError> {
+    for (i, line) in contents.split('\n').enumerate() {
+        let Some(directive) = line
+            .trim()
+            .strip_prefix("<!--")
+            .and_then(|line| line.strip_suffix("-->"))
+            .and_then(|comment| comment.trim().strip_prefix("specfmt:"))
+        else {
+            continue;
+        };
+        let wrap = directive
+            .trim()
+            .strip_prefix("wrap=")
+            .ok_or_else(|| format!("'{}' is not of the form wrap=COLUMNS", directive.trim()))
+            .and_then(parse_wrap)
+            .map_err(|message| {
+                SpecfmtError::WrapDirective(format!(
+                    "Invalid specfmt directive on line {}: {}",
+                    i + 1,
+                    message
+                ))
+            })?;
+        return Ok(Some(wrap));
+    }
+    Ok(None)
+}
+
+// A range of the spec to scope formatting to.
+#[derive(Clone, Copy, Debug)]
+enum Range {
+    // 1-based, inclusive line numbers.
+    Lines(usize, usize),
+    // 0-based byte offsets, the end being exclusive.
+    Bytes(usize, usize),
+}
+
+fn parse_line_range(range: &str) -> Result<Range, String> {
+    let error = || format!("'{}' is not a line range of the form START:END", range);
+    let (start, end) = range.split_once(':').ok_or_else(error)?;
+    let start: usize = start.parse().map_err(|_| error())?;
+    let end: usize = end.parse().map_err(|_| error())?;
+    if start == 0 || end < start {
+        return Err(error());
+    }
+    Ok(Range::Lines(start, end))
+}
+
+fn parse_byte_range(range: &str) -> Result<Range, String> {
+    let error = || format!("'{}' is not a byte range of the form START..END", range);
+    let (start, end) = range.split_once("..").ok_or_else(error)?;
+    let start: usize = start.parse().map_err(|_| error())?;
+    let end: usize = end.parse().map_err(|_| error())?;
+    if end < start {
+        return Err(error());
+    }
+    Ok(Range::Bytes(start, end))
+}
+
+impl Range {
+    // Returns the 0-based, inclusive range of lines of `contents` that this
+    // range covers.
+    fn lines(self, contents: &str) -> (usize, usize) {
+        let last_line = contents.matches('\n').count();
+        let line_of = |offset: usize| {
+            let offset = usize::min(offset, contents.len());
+            contents.as_bytes()[..offset]
+                .iter()
+                .filter(|&&byte| byte == b'\n')
+                .count()
+        };
+        match self {
+            Range::Lines(start, end) => (
+                usize::min(start - 1, last_line),
+                usize::min(end - 1, last_line),
+            ),
+            Range::Bytes(start, end) => (line_of(start), line_of(usize::max(end, start + 1) - 1)),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check that the spec is formatted, without modifying it. Intended for
+    /// continuous integration: emits GitHub Actions annotations, and writes the
+    /// changes that formatting would make to a diff file.
+    ///
+    /// Exit codes: 0 if the spec is formatted; 1 if it needs formatting; 2 on
+    /// usage errors or if the spec cannot be found or diffed; 3 if the spec is
+    /// formatted but some lines are still longer than the wrap width.
+    Ci(CiArgs),
+
+    /// Preview the changes that formatting would make in a browser, and pick
+    /// which of them to apply.
+    Preview(PreviewArgs),
+
+    /// Keep running, and format the specs (or buffers) that clients send in
+    /// JSON-RPC requests to a local port, to save the startup cost of a
+    /// process per request.
+    Daemon(DaemonArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct PreviewArgs {
+    /// The specification to format. Defaults to "source" or the unique .bs
+    /// file in the current directory.
+    filename: Option<String>,
+
+    #[command(flatten)]
+    discovery: DiscoveryOptions,
+
+    /// The local port to serve the preview on. Defaults to any free port.
+    #[arg(long, default_value_t = 0)]
+    port: u16,
+
+    #[command(flatten)]
+    format: FormatOptions,
+}
+
+#[derive(clap::Args, Debug)]
+struct DaemonArgs {
+    /// The local port to listen on. Defaults to any free port.
+    #[arg(long, default_value_t = 0)]
+    port: u16,
+}
+
+#[derive(clap::Args, Debug)]
+struct CiArgs {
+    #[command(flatten)]
+    specs: SpecFiles,
+
+    /// Where to write the diff of the changes that formatting would make. Only
+    /// written if a spec needs formatting.
+    #[arg(long, default_value = "specfmt.diff")]
+    diff_output: PathBuf,
+
+    #[command(flatten)]
+    format: FormatOptions,
+}
+
+// The exit codes of `specfmt ci`. CI configurations depend on these, so they
+// must never be renumbered. `EXIT_ERROR` is shared with clap, which uses it for
+// usage errors, and is what `clap::error::Error::exit()` exits with.
+const EXIT_FORMATTED: i32 = 0;
+const EXIT_NEEDS_FORMATTING: i32 = 1;
+const EXIT_ERROR: i32 = 2;
+const EXIT_OVERFLOW: i32 = 3;
+
+// Returns `options`, with the settings from the configuration file for `spec`
+// filling in for the flags missing from the command line `matches`.
+fn configured(options: &FormatOptions, matches: &ArgMatches, spec: &Path) -> FormatOptions {
+    let mut options = config::apply(options, matches, spec)
+        .unwrap_or_else(|err| exit_with_error(err, "config", Some(spec), options));
+    if unscope_outside_git(&mut options, spec) {
+        diagnostics::emit(
+            &mut io::stderr(),
+            &not_in_git_diagnostic(spec),
+            options.error_format(),
+        );
+    }
+    if let Some(diagnostic) = scope_on_base_branch(&mut options, spec) {
+        diagnostics::emit(&mut io::stderr(), &diagnostic, options.error_format());
+    }
+    options
+}
+
+// The warning that `unscope_outside_git()` made us format the entire `spec`.
+fn not_in_git_diagnostic(spec: &Path) -> Diagnostic {
+    Diagnostic {
+        severity: diagnostics::Severity::Warning,
+        code: "not-in-git",
+        file: Some(spec.display().to_string()),
+        lines: None,
+        message: String::from(
+            "The spec is not in a git repository, so there are no changes to scope formatting to. Formatting the entire spec.",
+        ),
+    }
+}
+
+// Makes `options` format the entire `spec` if they scope formatting to the
+// changes of its branch, but it isn't in a git repository (like a spec from an
+// exported tarball). Returns whether it did.
+fn unscope_outside_git(options: &mut FormatOptions, spec: &Path) -> bool {
+    let scoped = !options.full_spec && !options.staged && !options.has_range();
+    #[cfg(feature = "git")]
+    if scoped && spec.is_file() && !git::is_in_repository(spec) {
+        options.full_spec = true;
+        return true;
+    }
+    #[cfg(not(feature = "git"))]
+    let _ = (scoped, spec);
+    false
+}
+
+// Makes `options` scope formatting as `--on-base-branch` says to if they scope
+// it to the changes of the current branch of `spec`, but that is the base
+// branch, so it has no changes of its own. Otherwise, nothing would be
+// formatted, without saying why. Returns the warning suggesting what to do
+// instead, if formatting nothing is what `--on-base-branch` says to do.
+#[cfg(feature = "git")]
+fn scope_on_base_branch(options: &mut FormatOptions, spec: &Path) -> Option<Diagnostic> {
+    let scoped_to_branch = !options.full_spec
+        && !options.staged
+        && !options.working_tree
+        && options.base_commit.is_none()
+        && !options.has_range();
+    if !scoped_to_branch || !spec.is_file() {
+        return None;
+    }
+    let base_branch = git::on_base_branch(spec, &options.base_branch)?;
+
+    match options.on_base_branch {
+        OnBaseBranch::LastCommit => options.base_commit = Some(String::from("HEAD~1")),
+        OnBaseBranch::WorkingTree => options.working_tree = true,
+        OnBaseBranch::Suggest => {
+            return Some(Diagnostic {
+                severity: diagnostics::Severity::Warning,
+                code: "on-base-branch",
+                file: Some(spec.display().to_string()),
+                lines: None,
+                message: format!(
+                    "The spec is on its base branch '{}', so there are no changes of its own to scope formatting to. Pass --full-spec to format the entire spec, or --on-base-branch=last-commit or --on-base-branch=working-tree to format the changes of the last commit or the uncommitted changes.",
+                    base_branch
+                ),
+            })
+        }
+    }
+    None
+}
+
+#[cfg(not(feature = "git"))]
+fn scope_on_base_branch(_options: &mut FormatOptions, _spec: &Path) -> Option<Diagnostic> {
+    None
+}
+
+// Reports `diagnostic`, an error that stops us from formatting a spec, and
+// exits.
+fn exit_with(diagnostic: Diagnostic, options: &FormatOptions) -> ! {
+    diagnostics::emit(&mut io::stderr(), &diagnostic, options.error_format());
+    finish_diagnostics(&mut io::stdout(), options);
+    std::process::exit(EXIT_ERROR)
+}
+
+// Prints the diagnostics that formats like SARIF collect, once everything has
+// been reported.
+fn finish_diagnostics(out: &mut dyn Write, options: &FormatOptions) {
+    if let Err(error) = diagnostics::finish(out, options.error_format()) {
+        eprintln!("Error writing diagnostics: {}", error);
+        std::process::exit(EXIT_ERROR)
+    }
+}
+
+// Prints `report` to stdout, if one was asked for.
+fn print_report_document(report: &report::Report, options: &FormatOptions) {
+    if let Some(format) = options.report {
+        println!("{}", report.render(format));
+    }
+}
+
+// Like `exit_with()`, for an `error` with `file` (if any), reported with
+// `code`. In the human format we let clap report usage errors, along with the
+// usage.
+fn exit_with_error(
+    error: SpecfmtError,
+    code: &'static str,
+    file: Option<&Path>,
+    options: &FormatOptions,
+) -> ! {
+    if let SpecfmtError::Usage(error) = &error {
+        if options.error_format() == ErrorFormat::Human {
+            error.exit();
+        }
+    }
+    exit_with(
+        Diagnostic::error(
+            code,
+            file.map(|file| file.display().to_string()),
+            error.to_string(),
+        ),
+        options,
+    )
+}
+
+// Like `exit_with_error()`, for an `error` formatting `file`, reported with its
+// own code.
+fn exit_with_format_error(error: SpecfmtError, file: &Path, options: &FormatOptions) -> ! {
+    let code = error.code();
+    exit_with_error(error, code, Some(file), options)
+}
+
+// Reports an I/O `error` with `file` (if any) while doing `action`, and exits.
+fn exit_with_io_error(
+    action: &'static str,
+    file: Option<&Path>,
+    error: io::Error,
+    options: &FormatOptions,
+) -> ! {
+    // Reading a file or stdin into a string fails like this if it isn't UTF-8.
+    let error = match error.kind() {
+        io::ErrorKind::InvalidData => {
+            SpecfmtError::Parse(format!("Error {}: it is not valid UTF-8", action))
+        }
+        _ => SpecfmtError::io(action, error),
+    };
+    exit_with_error(error, "io", file, options)
+}
+
+fn default_filename(
+    filename: Option<String>,
+    patterns: &[String],
+) -> Result<PathBuf, SpecfmtError> {
+    let mut directory = PathBuf::from(".");
     if let Some(filename) = filename {
         let path = PathBuf::from(filename);
         // If you pass in a file, we simply use it.
@@ -78,144 +894,164 @@ fn default_filename(filename: Option<String>) -> Result<PathBuf, clap::error::Er
         // If you pass in something else (a valid directory, or something that
         // does not exist), then we'll use that that as the base for our search
         // for the appropriate spec file.
-        directory = String::from(path.to_str().unwrap());
+        directory = path;
     }
 
-    let source_path = directory.clone() + "/source";
-    if Path::new(&source_path).exists() {
-        return Ok(PathBuf::from(&source_path));
-    }
-    if let Ok(entries) = read_dir(directory) {
-        let bs_files: Vec<PathBuf> = entries
-            .filter_map(Result::ok)
-            .map(|entry| entry.path())
-            .filter(|path| {
-                if let Some(ext) = path.extension() {
-                    return ext == "bs";
-                }
-                false
-            })
-            .collect();
-        if bs_files.len() == 1 {
-            return Ok(bs_files[0].clone());
-        }
-        if bs_files.len() > 1 {
-            return Err(Args::command().error(
+    discovery::discover(&directory, patterns)
+        .spec
+        .map_err(|error| {
+            SpecfmtError::Usage(Args::command().error(
                 clap::error::ErrorKind::MissingRequiredArgument,
-                "Must specify filename: directory contains multiple .bs files",
-            ));
-        }
-    }
-    Err(Args::command().error(
-        clap::error::ErrorKind::MissingRequiredArgument,
-        "Must specify filename: directory doesn't contain \"source\" or .bs spec",
-    ))
-}
-
-fn assert_no_uncommitted_changes(path: &PathBuf) -> Result<(), clap::error::Error> {
-    // Extract the filename itself, as well as the directory from `path`.
-    assert!(path.is_file());
-    let filename_without_path = path.file_name().unwrap();
-    let directory = path.parent().unwrap();
-
-    let output = std::process::Command::new("git")
-        .arg("-C")
-        .arg(directory)
-        .arg("status")
-        .arg("--porcelain")
-        .arg(filename_without_path)
-        .output()
-        .expect("Failed to run `git status");
-
-    // This means that the spec we're targeting does not have uncommitted
-    // changes, so we're safe to proceed with rewrapping.
-    if output.stdout.is_empty() {
-        return Ok(());
-    }
-    Err(Args::command().error(
-        clap::error::ErrorKind::ValueValidation,
-        "Spec has uncommitted changes. Please commit your changes and try again.",
-    ))
-}
-
-// If there are no errors, this returns the computed diff of the target spec's
-// current branch and base branch (master or main). The output should be
-// filtered by `sanitized_diff_lines()`.
-fn git_diff(path: &Path) -> Result<String, clap::error::Error> {
-    // Extract the filename itself, as well as the directory from `path`.
-    assert!(path.is_file());
-    let filename_without_path = path.file_name().unwrap().to_str().unwrap();
-    let directory = path.parent().unwrap().to_str().unwrap();
-
-    // Get the name of the git branch that the spec is currently on.
-    let current_branch = std::process::Command::new("git")
-        .arg("-C")
-        .arg(directory)
-        .arg("branch")
-        .arg("--show-current")
-        .output()
-        .expect("Failed to run `git branch --show-current`");
-    let current_branch = String::from_utf8(current_branch.stdout).unwrap();
-    let current_branch = current_branch.trim();
-
-    // Get the base branch to compare `current_branch` to with in `git diff`. We
-    // expect it to be either `master` or `main`, and fail otherwise.
-    let branches = std::process::Command::new("git")
-        .arg("-C")
-        .arg(directory)
-        .arg("for-each-ref")
-        .arg("--format=%(refname:short)")
-        .output()
-        .expect("Failed to find the base branch to compare current branch '${}' with");
-    let branches = String::from_utf8(branches.stdout).unwrap();
-    let branches = branches.split('\n');
-
-    let mut base_branch: &str = "";
-    for branch in branches {
-        if branch == "master" || branch == "main" {
-            base_branch = branch;
-            break;
-        }
+                error + ". Run with --list-candidates to see the files that were considered.",
+            ))
+        })
+}
+
+// Prints the files that `default_filename()` considers in the directory
+// `filename` (or the current directory), and why each was or wasn't chosen.
+fn list_candidates(filename: Option<String>, patterns: &[String]) {
+    let directory = PathBuf::from(filename.unwrap_or_else(|| String::from(".")));
+    println!(
+        "Looking for a spec in '{}' matching '{}', in that order:",
+        directory.display(),
+        patterns.join("', '")
+    );
+    let discovery = discovery::discover(&directory, patterns);
+    for description in discovery::describe(&discovery, patterns) {
+        println!("  {}", description);
+    }
+    match discovery.spec {
+        Ok(spec) => println!("Found spec '{}'", spec.display()),
+        Err(error) => println!("{}", error),
     }
+}
 
-    // Could not find a branch named `master` or `main`. This configuration is
-    // considered invalid.
-    if base_branch == "" {
-        return Err(Args::command().error(
-            clap::error::ErrorKind::ValueValidation,
-            format!("Cannot find a 'master' or 'main' base branch with which to compare the current branch '{}'of the spec", current_branch),
-        ));
+// Reads the list of files given to `--files-from`. Lists containing a NUL byte
+// are NUL-separated (as produced by `git diff --name-only -z` or `find
+// -print0`); all others are newline-separated.
+fn read_file_list(list: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut contents = String::new();
+    if list == Path::new("-") {
+        io::stdin().read_to_string(&mut contents)?;
+    } else {
+        File::open(list)?.read_to_string(&mut contents)?;
     }
 
-    // Finally, compute the diff between `current_branch` and `base_branch`.
-    // Return the diff so we can inform the rewrapper of which lines to format
-    // (as to avoid rewrapping the *entire* spec).
-    let git_diff = std::process::Command::new("git")
-        .arg("-C")
-        .arg(directory)
-        .arg("diff")
-        .arg("-U0")
-        .arg(base_branch)
-        .arg(current_branch)
-        .arg(filename_without_path)
-        .output()
-        .expect("Failed to compute `git diff`");
+    let separator = if contents.contains('\0') { '\0' } else { '\n' };
+    Ok(contents
+        .split(separator)
+        .map(|entry| entry.trim_end_matches('\r'))
+        .filter(|entry| !entry.is_empty())
+        .map(PathBuf::from)
+        .filter(|path| {
+            // Lists generated from `git diff` include deleted files.
+            if !path.is_file() {
+                println!("Skipping '{}': not a file", path.display());
+                return false;
+            }
+            true
+        })
+        .collect())
+}
 
-    Ok(String::from_utf8(git_diff.stdout).unwrap())
+impl SpecFiles {
+    fn filenames(self) -> Result<Vec<PathBuf>, SpecfmtError> {
+        match self.files_from {
+            Some(list) => read_file_list(&list).map_err(|error| {
+                SpecfmtError::Usage(Args::command().error(
+                    clap::error::ErrorKind::Io,
+                    format!("Cannot read file list '{}': {}", list.display(), error),
+                ))
+            }),
+            None if self.recursive => {
+                let directory = PathBuf::from(self.filename.unwrap_or_else(|| String::from(".")));
+                if directory.is_file() {
+                    return Ok(vec![directory]);
+                }
+                let patterns = &self.discovery.spec_pattern;
+                match discovery::discover_recursive(&directory, patterns) {
+                    Ok(specs) if specs.is_empty() => Err(Args::command()
+                        .error(
+                            clap::error::ErrorKind::MissingRequiredArgument,
+                            format!(
+                                "No file matching '{}' found in '{}' or its subdirectories",
+                                patterns.join("' or '"),
+                                directory.display()
+                            ),
+                        )
+                        .into()),
+                    Ok(specs) => Ok(specs),
+                    Err(error) => Err(Args::command()
+                        .error(
+                            clap::error::ErrorKind::Io,
+                            format!("Cannot read directory '{}': {}", directory.display(), error),
+                        )
+                        .into()),
+                }
+            }
+            None => Ok(vec![default_filename(
+                self.filename,
+                &self.discovery.spec_pattern,
+            )?]),
+        }
+    }
 }
 
 // Takes the `String` output of `git_diff` above, and filters out irrelevant
 // lines. Cannot be a part of `git_diff` because this returns a vector of string
 // slices (for efficiency) on top of strings allocated inside of `git_diff`.
-fn sanitized_diff_lines(diff: &String) -> Vec<&str> {
-    diff.split("\n")
-        .enumerate()
-        // Strip the first 5 version control lines, and only consider lines
-        // prefixed with "+" that are more than one character long.
-        .filter(|&(i, line)| i > 4 && line.starts_with("+") && line.len() > 1)
-        // Remove the "+" version control prefix.
-        .map(|(_, line)| &line[1..])
-        .collect()
+// With `word_diff`, the lines of hunks that only edit words in place are
+// filtered out too (see `is_word_edit()`).
+fn sanitized_diff_lines(diff: &str, word_diff: bool) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut hunk: Vec<&str> = Vec::new();
+    // Strip the version control lines before the first hunk (of which there are
+    // more for new and renamed files).
+    for line in diff
+        .split("\n")
+        .skip_while(|line| !line.starts_with("@@"))
+        .chain(std::iter::once("@@"))
+    {
+        if !line.starts_with("@@") {
+            hunk.push(line);
+            continue;
+        }
+        if !(word_diff && is_word_edit(&hunk)) {
+            // Only consider lines prefixed with "+" that are more than one
+            // character long, and remove the "+" version control prefix.
+            lines.extend(
+                hunk.iter()
+                    .filter(|line| line.starts_with("+") && line.len() > 1)
+                    .map(|line| &line[1..]),
+            );
+        }
+        hunk.clear();
+    }
+    lines
+}
+
+// Returns whether the lines of a diff `hunk` only edit words in place, like
+// fixing a typo or renaming a term, as opposed to changing the whitespace or
+// structure of the lines, which is what `git diff --word-diff=porcelain` would
+// show as changed newlines. That is, each removed line is replaced by one added
+// line with the same indentation and the same number of words. Rewrapping the
+// paragraph of such a hunk would only add noise to the review of the edit.
+fn is_word_edit(hunk: &[&str]) -> bool {
+    let removed: Vec<&str> = hunk
+        .iter()
+        .filter_map(|line| line.strip_prefix('-'))
+        .collect();
+    let added: Vec<&str> = hunk
+        .iter()
+        .filter_map(|line| line.strip_prefix('+'))
+        .collect();
+    let indentation = |line: &str| line.len() - line.trim_start().len();
+    !removed.is_empty()
+        && removed.len() == added.len()
+        && removed.iter().zip(&added).all(|(old, new)| {
+            indentation(old) == indentation(new)
+                && old.split_whitespace().count() == new.split_whitespace().count()
+        })
 }
 
 // Marks all of the lines in `lines` as needing format if and only if they
@@ -223,88 +1059,1150 @@ fn sanitized_diff_lines(diff: &String) -> Vec<&str> {
 // the *contents* of the lines in `diff` with `lines`, not the actual line
 // numbers. See https://github.com/domfarolino/specfmt/issues/7.
 fn apply_diff(lines: &mut Vec<Line>, diff: &Vec<&str>) {
-    if diff.len() == 0 {
+    if diff.is_empty() {
         return;
     }
 
     let mut iter = diff.iter().peekable();
     for line in lines {
-        if line.contents == **iter.peek().unwrap() {
+        // The diff of a CRLF spec has the CRs that the lines no longer do.
+        if line.contents.trim_end_matches('\r') == iter.peek().unwrap().trim_end_matches('\r') {
             line.should_format = true;
             iter.next();
         }
 
-        if iter.peek() == None {
+        if iter.peek().is_none() {
             break;
         }
     }
 }
 
-fn main() {
-    let args = Args::parse();
-    let filename = default_filename(args.filename).unwrap_or_else(|err| err.exit());
+// Computes the lines of `filename` that formatting is scoped to, unless we're
+// formatting the entire spec.
+#[cfg_attr(not(feature = "git"), allow(unused_variables))]
+fn try_scoped_diff(filename: &Path, options: &FormatOptions) -> Result<String, SpecfmtError> {
+    if options.full_spec || options.has_range() {
+        return Ok(String::from(""));
+    }
 
-    if !args.force {
-        assert_no_uncommitted_changes(&filename).unwrap_or_else(|err| err.exit());
+    #[cfg(feature = "git")]
+    if options.staged {
+        git::git_diff_staged(filename)
+    } else if options.working_tree {
+        git::git_diff_working_tree(filename)
+    } else if let Some(commit) = &options.base_commit {
+        git::git_diff_since(filename, commit)
+    } else {
+        git::git_diff(filename, &options.base_branch)
     }
+    #[cfg(not(feature = "git"))]
+    Err(without_git().into())
+}
 
-    let diff = if !args.full_spec {
-        git_diff(&filename).unwrap_or_else(|err| err.exit())
+// Like `try_scoped_diff()`, but exits if scoping fails.
+fn scoped_diff(filename: &Path, options: &FormatOptions) -> String {
+    try_scoped_diff(filename, options)
+        .unwrap_or_else(|err| exit_with_error(err, "scoping", Some(filename), options))
+}
+
+// Scoping formatting to the changes in a git repository (the default) is not
+// possible in builds without the `git` feature.
+#[cfg(not(feature = "git"))]
+fn without_git() -> clap::error::Error {
+    Args::command().error(
+        clap::error::ErrorKind::InvalidValue,
+        "specfmt was built without git support; pass --full-spec or --range",
+    )
+}
+
+// Formats `contents` according to `options`, scoped to the lines in `diff`.
+fn format_spec(
+    contents: String,
+    diff: &Vec<&str>,
+    options: &FormatOptions,
+) -> Result<rewrapper::Rewrapped, SpecfmtError> {
+    let range = options
+        .range
+        .or(options.range_bytes)
+        .map(|range| range.lines(&contents));
+    let exact_range = match &options.section {
+        Some(section) => Some(sections::section_lines(&contents, section).ok_or_else(|| {
+            Args::command().error(
+                clap::error::ErrorKind::InvalidValue,
+                format!("Cannot find a section with the heading or id '{}'", section),
+            )
+        })?),
+        None => options.lines.map(|range| range.lines(&contents)),
+    };
+    // A byte order mark isn't part of the first line, so it doesn't hide a code
+    // fence or heading there from formatting. It's put back afterwards.
+    let bom = contents.starts_with(BOM);
+    let contents = if bom {
+        contents[BOM.len_utf8()..].to_string()
     } else {
-        String::from("")
+        contents
     };
-    let diff = sanitized_diff_lines(&diff);
-
-    let (file, file_as_string): (File, String) = match read_file(&filename) {
-        Ok((file, string)) => {
-            println!("Successfully read file '{}'", filename.display());
-            (file, string)
-        }
-        Err(error) => panic!("Error opening file '{}': {:?}", filename.display(), error),
+    // Lines are formatted without the `\r` of a CRLF ending, which is put back
+    // on every line afterwards. Unless asked to convert them, LF specs keep
+    // any stray CRs as they are.
+    let crlf = options.line_ending.is_crlf(&contents);
+    let contents = if crlf || options.line_ending == LineEnding::Lf {
+        contents.replace("\r\n", "\n")
+    } else {
+        contents
     };
 
-    let mut lines: Vec<Line> = file_as_string
+    let mut lines: Vec<Line> = contents
         .split("\n")
         .map(|line_contents| Line {
             // If we are to format the entire spec, then mark each line as
             // subject to formatting.
-            should_format: args.full_spec,
+            should_format: options.full_spec,
             contents: line_contents,
         })
         .collect();
 
-    apply_diff(&mut lines, &diff);
+    apply_diff(&mut lines, diff);
 
-    let num_lines_to_format = if args.full_spec {
+    let mut num_lines_to_format = if options.full_spec {
         lines.len()
+    } else if let Some((start, end)) = range {
+        apply_range(&mut lines, start, end)
+    } else if let Some((start, end)) = exact_range {
+        apply_lines(&mut lines, start, end)
     } else {
         diff.len()
     };
 
+    // JSON blocks are only pretty-printed if they are subject to formatting,
+    // and all of their lines are afterwards.
+    let json_formatted;
+    if options.format_json {
+        let in_scope: Vec<bool> = lines.iter().map(|line| line.should_format).collect();
+        let (formatted, in_scope) = json::format_json_blocks_in_scope(&contents, &in_scope);
+        json_formatted = formatted;
+        lines = json_formatted
+            .split("\n")
+            .zip(in_scope)
+            .map(|(contents, should_format)| Line {
+                should_format,
+                contents,
+            })
+            .collect();
+        num_lines_to_format = lines.iter().filter(|line| line.should_format).count();
+    }
+
+    let metadata = metadata::parse(&contents);
+    let wrap = match options.wrap {
+        Some(wrap) => Some(wrap),
+        None => match wrap_directive(&contents)? {
+            Some(wrap) => Some(wrap),
+            None => metadata.wrap?,
+        },
+    };
+    let column_length = match wrap.unwrap_or(Wrap::Columns(DEFAULT_WRAP)) {
+        Wrap::Columns(columns) => columns,
+        Wrap::Auto => rewrapper::detect_column_length(&contents).unwrap_or(DEFAULT_WRAP),
+    };
+
     // Initiate unwrapping/rewrapping.
-    let rewrapped_lines = rewrapper::rewrap_lines(lines, num_lines_to_format, args.wrap);
+    let mut rewrap_options = rewrapper::RewrapOptions::default()
+        .column_length(column_length)
+        .wrap_attributes(options.wrap_attributes)
+        .preserve_breaks_before(options.preserve_breaks_before.clone())
+        .exempt_tags(options.exempt_tags.clone())
+        .atomic_elements(options.atomic_elements.clone())
+        .continuation_indent(options.continuation_indent)
+        .continuation_rules(options.continuation_rules.clone())
+        .wrap_algorithm(options.wrap_algorithm)
+        .respect_sentence_breaks(options.respect_sentence_breaks)
+        .unwrap_paragraphs(!options.no_unwrap)
+        .break_cjk(options.break_cjk)
+        .rendered_entity_width(options.rendered_entity_width)
+        .tab_width(options.tab_width)
+        .expand_tabs(options.expand_tabs)
+        .strip_trailing_whitespace(options.strip_trailing_whitespace)
+        .reindent(options.reindent)
+        .markdown(!options.no_markdown && metadata.markdown.unwrap_or(true));
+    rewrap_options.paragraph_content = options.paragraph_content;
+    // Lines outside of an exact `--lines` range or `--section` aren't joined
+    // onto it, unless `--scope` says how far to reach.
+    rewrap_options.scope = options
+        .scope
+        .or(exact_range.map(|_| rewrapper::Scope::Line));
+    let mut rewrapped = rewrapper::rewrap_lines(lines, num_lines_to_format, &rewrap_options);
 
-    // Join all lines and write to file.
-    let file_as_string = rewrapped_lines.join("\n");
-    match write_file(file, file_as_string) {
+    let lines = &rewrapped.lines;
+    rewrapped.report.warnings.retain(|warning| match warning {
+        rewrapper::Warning::Overflow { line } => !options
+            .allow_long_line
+            .iter()
+            .any(|pattern| pattern.is_match(&lines[*line])),
+        _ => true,
+    });
+    if options.ensure_final_newline && rewrapped.lines.last().is_some_and(|line| !line.is_empty()) {
+        rewrapped.lines.push(String::new());
+    }
+    if crlf {
+        if let Some((_, lines)) = rewrapped.lines.split_last_mut() {
+            for line in lines {
+                line.push('\r');
+            }
+        }
+    }
+    if bom {
+        rewrapped.lines[0].insert(0, BOM);
+    }
+    Ok(rewrapped)
+}
+
+fn warning_diagnostic(
+    warning: &rewrapper::Warning,
+    filename: &Path,
+    report: &rewrapper::FormatReport,
+) -> Diagnostic {
+    let (code, line, message) = match warning {
+        rewrapper::Warning::UnclosedExemptBlock { tag, line } => (
+            "unclosed-exempt-block",
+            *line,
+            if tag.starts_with(['`', '~']) {
+                format!(
+                    "The code fence {} is never closed, so the rest of the spec is not formatted.",
+                    tag
+                )
+            } else {
+                format!(
+                    "`{}` is never closed, so the rest of the spec is not formatted.",
+                    tag
+                )
+            },
+        ),
+        rewrapper::Warning::Overflow { line } => (
+            "overflow",
+            *line,
+            format!("Line is longer than {} columns.", report.column_length),
+        ),
+    };
+    Diagnostic {
+        severity: diagnostics::Severity::Warning,
+        code,
+        file: Some(filename.display().to_string()),
+        lines: Some((line + 1, line + 1)),
+        message,
+    }
+}
+
+fn print_report(
+    out: &mut dyn Write,
+    filename: &Path,
+    report: &rewrapper::FormatReport,
+    options: &FormatOptions,
+) {
+    if options.error_format().is_verbose() {
+        let width = match report.column_length {
+            0 => String::from("no column limit"),
+            wrap => format!("{} characters", wrap),
+        };
+        writeln!(
+            out,
+            "The spec has {} lines total. We tried to wrap {} lines to {}, changing {} lines",
+            report.total_lines, report.lines_to_format, width, report.lines_changed
+        )
+        .unwrap();
+    }
+    for warning in &report.warnings {
+        let diagnostic = warning_diagnostic(warning, filename, report);
+        diagnostics::emit(out, &diagnostic, options.error_format());
+    }
+}
+
+// Checks that `filename` is formatted, appending the changes that formatting
+// would make to `unified_diffs` and `report`. Returns the `specfmt ci` exit code
+// for it.
+fn check_file(
+    filename: &Path,
+    options: &FormatOptions,
+    unified_diffs: &mut String,
+    report: &mut report::Report,
+) -> i32 {
+    let diff = scoped_diff(filename, options);
+    let diff = sanitized_diff_lines(&diff, options.word_diff);
+
+    let (_file, file_as_string) = read_file(filename)
+        .unwrap_or_else(|error| exit_with_io_error("opening file", Some(filename), error, options));
+    let rewrapped = format_spec(file_as_string.clone(), &diff, options)
+        .unwrap_or_else(|err| exit_with_format_error(err, filename, options));
+    let formatted = rewrapped.lines.join("\n");
+    let error_format = options.error_format();
+    let mut out = options.output();
+
+    let path = filename.display().to_string();
+    report.add(&path, &file_as_string, &formatted, &rewrapped.report);
+    let hunks = diff::hunks(&file_as_string, &formatted);
+    if !hunks.is_empty() {
+        for hunk in &hunks {
+            let (start, end) = hunk.changed_old_lines();
+            let diagnostic = Diagnostic::error(
+                "needs-formatting",
+                Some(path.clone()),
+                String::from("This section of the spec needs formatting. Run `specfmt` to fix it."),
+            );
+            let diagnostic = diagnostic.at_lines(start, end);
+            diagnostics::emit(&mut *out, &diagnostic, error_format);
+        }
+        unified_diffs.push_str(&diff::unified_diff(
+            &file_as_string,
+            &formatted,
+            &diff_path(filename),
+        ));
+        if error_format.is_verbose() {
+            writeln!(out, "'{}' needs formatting", path).unwrap();
+            if !options.full_spec {
+                print_changed_sections(&mut *out, &file_as_string, &hunks);
+            }
+        }
+        return EXIT_NEEDS_FORMATTING;
+    }
+
+    // The spec is formatted, so lines in `formatted` correspond one-to-one
+    // with lines on disk.
+    let mut exit_code = EXIT_FORMATTED;
+    for warning in &rewrapped.report.warnings {
+        let mut diagnostic = warning_diagnostic(warning, filename, &rewrapped.report);
+        if let rewrapper::Warning::Overflow { .. } = warning {
+            exit_code = EXIT_OVERFLOW;
+            diagnostic.severity = diagnostics::Severity::Error;
+        }
+        diagnostics::emit(&mut *out, &diagnostic, error_format);
+    }
+    if exit_code == EXIT_OVERFLOW {
+        return EXIT_OVERFLOW;
+    }
+
+    if error_format.is_verbose() {
+        writeln!(out, "'{}' is formatted", path).unwrap();
+    }
+    EXIT_FORMATTED
+}
+
+// Runs `check_file()` on each of `filenames`, and returns the exit code for all
+// of them.
+fn check_files(
+    filenames: &[PathBuf],
+    options: &FormatOptions,
+    matches: &ArgMatches,
+    unified_diffs: &mut String,
+    report: &mut report::Report,
+) -> i32 {
+    // Needing formatting takes precedence over overflowing lines, since
+    // formatting may well fix them.
+    let mut exit_code = EXIT_FORMATTED;
+    for filename in filenames {
+        let options = configured(options, matches, filename);
+        exit_code = match (
+            exit_code,
+            check_file(filename, &options, unified_diffs, report),
+        ) {
+            (EXIT_NEEDS_FORMATTING, _) | (_, EXIT_NEEDS_FORMATTING) => EXIT_NEEDS_FORMATTING,
+            (EXIT_OVERFLOW, _) | (_, EXIT_OVERFLOW) => EXIT_OVERFLOW,
+            _ => EXIT_FORMATTED,
+        };
+    }
+    exit_code
+}
+
+fn run_ci(mut args: CiArgs, matches: &ArgMatches) -> i32 {
+    args.format.error_format.get_or_insert(ErrorFormat::Github);
+    let filenames = args
+        .specs
+        .filenames()
+        .unwrap_or_else(|err| exit_with_error(err, "discovery", None, &args.format));
+
+    let mut unified_diffs = String::new();
+    let mut report = report::Report::default();
+    let exit_code = check_files(
+        &filenames,
+        &args.format,
+        matches,
+        &mut unified_diffs,
+        &mut report,
+    );
+    finish_diagnostics(&mut *args.format.output(), &args.format);
+    if !unified_diffs.is_empty() {
+        if let Err(error) = std::fs::write(&args.diff_output, unified_diffs) {
+            exit_with_io_error("writing diff", Some(&args.diff_output), error, &args.format);
+        }
+        if args.format.error_format().is_verbose() {
+            let mut out = args.format.output();
+            writeln!(out, "Wrote the changes to '{}'", args.diff_output.display()).unwrap();
+        }
+    }
+    print_report_document(&report, &args.format);
+    exit_code
+}
+
+fn run_preview(args: PreviewArgs, matches: &ArgMatches) {
+    let filename = default_filename(args.filename, &args.discovery.spec_pattern)
+        .unwrap_or_else(|err| exit_with_error(err, "discovery", None, &args.format));
+    let options = configured(&args.format, matches, &filename);
+    let diff = scoped_diff(&filename, &options);
+    let diff = sanitized_diff_lines(&diff, options.word_diff);
+
+    let (_file, file_as_string) = read_file(&filename).unwrap_or_else(|error| {
+        exit_with_io_error("opening file", Some(&filename), error, &options)
+    });
+    let rewrapped = format_spec(file_as_string.clone(), &diff, &options)
+        .unwrap_or_else(|err| exit_with_format_error(err, &filename, &options));
+    let hunks = diff::hunks(&file_as_string, &rewrapped.lines.join("\n"));
+    if hunks.is_empty() {
+        println!("'{}' is formatted", filename.display());
+        return;
+    }
+
+    let selected = preview::serve(&filename.display().to_string(), &hunks, args.port)
+        .unwrap_or_else(|error| exit_with_io_error("serving the preview", None, error, &options));
+    match write_file(
+        &filename,
+        diff::apply_hunks(&file_as_string, &hunks, &selected),
+    ) {
         Ok(_) => println!("Write succeeded"),
-        Err(error) => panic!("Error writing file '{}': {:?}", filename.display(), error),
+        Err(error) => exit_with_io_error("writing file", Some(&filename), error, &options),
+    }
+}
+
+// How the diffs we print name `filename`: relative to the root of its git
+// repository, so that `git apply` can apply them, or as given otherwise.
+fn diff_path(filename: &Path) -> String {
+    #[cfg(feature = "git")]
+    if let Some(path) = git::repository_path(filename) {
+        return path;
+    }
+    let path = filename.display().to_string();
+    match path.strip_prefix("./") {
+        Some(path) => path.to_string(),
+        None => path,
     }
 }
 
+// Where `--backup` saves the contents of `filename` before formatting it.
+fn backup_path(filename: &Path) -> PathBuf {
+    let mut path = filename.as_os_str().to_owned();
+    path.push(".specfmt.orig");
+    PathBuf::from(path)
+}
+
+// Restores `filename` from the backup that `--backup` saved, which is deleted.
+fn revert_file(filename: &Path, options: &FormatOptions) {
+    let backup = backup_path(filename);
+    if !backup.is_file() {
+        exit_with(
+            Diagnostic::error(
+                "revert",
+                Some(filename.display().to_string()),
+                format!(
+                    "No backup to revert to: '{}' does not exist",
+                    backup.display()
+                ),
+            ),
+            options,
+        );
+    }
+    if let Err(error) = std::fs::rename(&backup, filename) {
+        exit_with_io_error("restoring backup", Some(&backup), error, options);
+    }
+    if options.error_format().is_verbose() {
+        let mut out = options.output();
+        writeln!(
+            out,
+            "Restored '{}' from '{}'",
+            filename.display(),
+            backup.display()
+        )
+        .unwrap();
+    }
+}
+
+// Colors the lines of the unified `diff` like `git diff` does: headers in
+// bold, hunk headers in cyan, and removed and added lines in red and green.
+fn colorize_diff(diff: &str) -> String {
+    let mut colorized = String::new();
+    for line in diff.split_inclusive('\n') {
+        let (contents, newline) = match line.strip_suffix('\n') {
+            Some(contents) => (contents, "\n"),
+            None => (line, ""),
+        };
+        let color = if contents.starts_with("---") || contents.starts_with("+++") {
+            "\x1b[1m"
+        } else if contents.starts_with('@') {
+            "\x1b[36m"
+        } else if contents.starts_with('-') {
+            "\x1b[31m"
+        } else if contents.starts_with('+') {
+            "\x1b[32m"
+        } else {
+            colorized.push_str(line);
+            continue;
+        };
+        colorized.push_str(&format!("{}{}\x1b[0m{}", color, contents, newline));
+    }
+    colorized
+}
+
+fn print_changed_sections(out: &mut dyn Write, spec: &str, hunks: &[diff::Hunk]) {
+    writeln!(out, "Sections with formatting changes:").unwrap();
+    for section in sections::changed_sections(spec, hunks) {
+        writeln!(out, "  - {}", section).unwrap();
+    }
+}
+
+// Asks which of the changes from `original` to `formatted` to apply, and returns
+// `original` with just those applied. Sets `quit` if the user quit.
+fn select_changes(
+    filename: &Path,
+    original: &str,
+    formatted: String,
+    quit: &mut bool,
+    out: &mut dyn Write,
+    options: &FormatOptions,
+) -> String {
+    let hunks = diff::hunks(original, &formatted);
+    if hunks.is_empty() {
+        return formatted;
+    }
+    let path = filename.display().to_string();
+    let selection = interactive::select(&path, &hunks, &mut io::stdin().lock(), out)
+        .unwrap_or_else(|error| exit_with_io_error("selecting changes", None, error, options));
+    *quit = selection.quit;
+    diff::apply_hunks(original, &hunks, &selection.selected)
+}
+
+// Where `format_file()` puts the formatted spec.
+enum Destination {
+    // Overwrite the spec.
+    InPlace,
+    // Print the changes that formatting would make as a unified diff to stdout,
+    // colored if `color`, for `--dry-run`.
+    Diff { color: bool },
+    // Write the formatted spec to another file, or to stdout if it's "-".
+    Output(PathBuf),
+}
+
+impl Destination {
+    // Whether the spec goes to stdout, so that everything else must go to
+    // stderr.
+    fn is_stdout(&self) -> bool {
+        match self {
+            Destination::InPlace => false,
+            Destination::Diff { .. } => true,
+            Destination::Output(path) => path == Path::new("-"),
+        }
+    }
+}
+
+// Formats `filename`, putting the result in `destination`. If `interactive` is
+// given, only the changes the user selects are made, and it's set if the user
+// quit. Returns whether formatting changed the spec.
+fn format_file(
+    filename: &Path,
+    force: bool,
+    destination: &Destination,
+    interactive: Option<&mut bool>,
+    options: &FormatOptions,
+    report: &mut report::Report,
+) -> bool {
+    let in_place = matches!(destination, Destination::InPlace);
+    // Staged and working tree changes are uncommitted by definition, and
+    // writing elsewhere doesn't overwrite any changes.
+    #[cfg(feature = "git")]
+    if !force && in_place && !options.staged && !options.working_tree {
+        git::assert_no_uncommitted_changes(filename).unwrap_or_else(|err| {
+            exit_with_error(err, "uncommitted-changes", Some(filename), options)
+        });
+    }
+    #[cfg(not(feature = "git"))]
+    let _ = (force, in_place);
+
+    let diff = scoped_diff(filename, options);
+    let diff = sanitized_diff_lines(&diff, options.word_diff);
+
+    // When printing the diff or formatted spec, stdout is reserved for it, so
+    // everything else goes to stderr.
+    let mut out: Box<dyn Write> = if destination.is_stdout() {
+        Box::new(io::stderr())
+    } else {
+        options.output()
+    };
+    let verbose = options.error_format().is_verbose();
+    let file_as_string = match read_file(filename) {
+        Ok((_file, string)) => {
+            if verbose {
+                writeln!(out, "Successfully read file '{}'", filename.display()).unwrap();
+            }
+            string
+        }
+        Err(error) => exit_with_io_error("opening file", Some(filename), error, options),
+    };
+
+    let original = file_as_string.clone();
+    let rewrapped = format_spec(file_as_string, &diff, options)
+        .unwrap_or_else(|err| exit_with_format_error(err, filename, options));
+    print_report(&mut *out, filename, &rewrapped.report, options);
+
+    // Join all lines and write to file.
+    let file_as_string = rewrapped.lines.join("\n");
+    let file_as_string = match interactive {
+        Some(quit) => select_changes(
+            filename,
+            &original,
+            file_as_string,
+            quit,
+            &mut *out,
+            options,
+        ),
+        None => file_as_string,
+    };
+    report.add(
+        &filename.display().to_string(),
+        &original,
+        &file_as_string,
+        &rewrapped.report,
+    );
+    match destination {
+        Destination::Diff { color } => {
+            let unified_diff = diff::unified_diff(&original, &file_as_string, &diff_path(filename));
+            if *color {
+                print!("{}", colorize_diff(&unified_diff));
+            } else {
+                print!("{}", unified_diff);
+            }
+        }
+        Destination::Output(path) if path == Path::new("-") => {
+            if let Err(error) = io::stdout().write_all(file_as_string.as_bytes()) {
+                exit_with_io_error("writing stdout", None, error, options);
+            }
+        }
+        Destination::InPlace | Destination::Output(_) => {
+            let path = match destination {
+                Destination::Output(path) => path,
+                _ => filename,
+            };
+            match write_file(path, file_as_string.clone()) {
+                Ok(_) if verbose => writeln!(out, "Write succeeded").unwrap(),
+                Ok(_) => {}
+                Err(error) => exit_with_io_error("writing file", Some(path), error, options),
+            }
+        }
+    }
+
+    if verbose && !options.full_spec {
+        let hunks = diff::hunks(&original, &file_as_string);
+        if !hunks.is_empty() {
+            print_changed_sections(&mut *out, &original, &hunks);
+        }
+    }
+
+    #[cfg(feature = "git")]
+    if options.staged && !options.no_restage && in_place {
+        git::restage(filename, &original, &file_as_string)
+            .unwrap_or_else(|err| exit_with_error(err, "staging", Some(filename), options));
+    }
+    file_as_string != original
+}
+
+// Formats the spec read from stdin, writing the result to stdout. `filename` is
+// where the spec lives, which formatting is scoped to the git changes of unless
+// formatting the entire spec or a range. Since stdout is reserved for the spec,
+// everything else goes to stderr. Returns whether formatting changed the spec.
+fn format_stdin(filename: &Path, options: &FormatOptions) -> bool {
+    let scoped = !options.full_spec && !options.has_range();
+    if scoped && !filename.is_file() {
+        let error = Args::command().error(
+            clap::error::ErrorKind::ValueValidation,
+            format!(
+                "Cannot scope formatting to the changes to '{}', which does not exist. Pass --full-spec or --range instead.",
+                filename.display()
+            ),
+        );
+        exit_with_error(error.into(), "scoping", Some(filename), options);
+    }
+    let diff = scoped_diff(filename, options);
+    let diff = sanitized_diff_lines(&diff, options.word_diff);
+
+    let mut file_as_string = String::new();
+    if let Err(error) = io::stdin().read_to_string(&mut file_as_string) {
+        exit_with_io_error("reading stdin", None, error, options);
+    }
+
+    let original = file_as_string.clone();
+    let rewrapped = format_spec(file_as_string, &diff, options)
+        .unwrap_or_else(|err| exit_with_format_error(err, filename, options));
+    print_report(&mut io::stderr(), filename, &rewrapped.report, options);
+
+    let file_as_string = rewrapped.lines.join("\n");
+    if let Err(error) = io::stdout().write_all(file_as_string.as_bytes()) {
+        exit_with_io_error("writing stdout", None, error, options);
+    }
+
+    if scoped && options.error_format().is_verbose() {
+        let hunks = diff::hunks(&original, &file_as_string);
+        if !hunks.is_empty() {
+            print_changed_sections(&mut io::stderr(), &original, &hunks);
+        }
+    }
+    file_as_string != original
+}
+
+// Exits after formatting, with `EXIT_NEEDS_FORMATTING` if a spec `changed` and
+// `--fail-on-change` was given. Unlike returning from `main()`, exiting doesn't
+// flush stdout, which may hold a formatted spec without a trailing newline.
+fn exit_after_formatting(changed: bool, fail_on_change: bool) -> ! {
+    let _ = io::stdout().flush();
+    if changed && fail_on_change {
+        std::process::exit(EXIT_NEEDS_FORMATTING)
+    }
+    std::process::exit(EXIT_FORMATTED)
+}
+
+// Marks the lines of the paragraphs enclosing lines `start` to `end` (0-based,
+// inclusive) as needing format. The whole paragraphs are formatted, so that the
+// result is the same as formatting the entire spec would produce for them.
+// Returns the number of lines marked.
+fn apply_range(lines: &mut [Line], start: usize, end: usize) -> usize {
+    let (start, end) = rewrapper::enclosing_paragraphs(lines, start, end);
+    for line in &mut lines[start..=end] {
+        line.should_format = true;
+    }
+    end - start + 1
+}
+
+// Marks exactly lines `start` to `end` (0-based, inclusive) as needing format.
+// Returns the number of lines marked.
+fn apply_lines(lines: &mut [Line], start: usize, end: usize) -> usize {
+    for line in &mut lines[start..=end] {
+        line.should_format = true;
+    }
+    end - start + 1
+}
+
+fn main() {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    match args.command {
+        Some(Command::Ci(ci_args)) => {
+            let matches = matches.subcommand_matches("ci").unwrap();
+            std::process::exit(run_ci(ci_args, matches))
+        }
+        Some(Command::Preview(preview_args)) => {
+            let matches = matches.subcommand_matches("preview").unwrap();
+            return run_preview(preview_args, matches);
+        }
+        Some(Command::Daemon(daemon_args)) => {
+            if let Err(error) = daemon::run(daemon_args.port) {
+                exit_with_io_error("serving requests", None, error, &args.format);
+            }
+            return;
+        }
+        None => {}
+    }
+
+    if args.lsp {
+        let exit_code = lsp::run(&args.format, &matches).unwrap_or_else(|error| {
+            exit_with_io_error("serving the language server", None, error, &args.format)
+        });
+        std::process::exit(exit_code);
+    }
+
+    if args.list_candidates {
+        return list_candidates(args.specs.filename, &args.specs.discovery.spec_pattern);
+    }
+
+    if let Some(stdin_filepath) = args.stdin_filepath {
+        let options = configured(&args.format, &matches, &stdin_filepath);
+        let changed = format_stdin(&stdin_filepath, &options);
+        finish_diagnostics(&mut io::stderr(), &options);
+        exit_after_formatting(changed, args.fail_on_change);
+    }
+
+    // A spec read from stdin has no changes to scope formatting to, so unless
+    // it's given a range, we format all of it.
+    if args.specs.filename.as_deref() == Some("-") {
+        if args.check
+            || args.dry_run
+            || args.interactive
+            || args.output.is_some()
+            || args.format.staged
+            || args.format.report.is_some()
+        {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "Cannot combine formatting stdin (\"-\") with --check, --dry-run, --interactive, --output, --staged, or --report",
+                )
+                .exit();
+        }
+        if !args.format.has_range() {
+            args.format.full_spec = true;
+        }
+        let options = configured(&args.format, &matches, Path::new("<stdin>"));
+        let changed = format_stdin(Path::new("<stdin>"), &options);
+        finish_diagnostics(&mut io::stderr(), &options);
+        exit_after_formatting(changed, args.fail_on_change);
+    }
+
+    let filenames = args
+        .specs
+        .filenames()
+        .unwrap_or_else(|err| exit_with_error(err, "discovery", None, &args.format));
+    if args.revert {
+        for filename in filenames {
+            revert_file(&filename, &args.format);
+        }
+        return;
+    }
+    if args.check || args.patch.is_some() {
+        let mut unified_diffs = String::new();
+        let mut report = report::Report::default();
+        let exit_code = check_files(
+            &filenames,
+            &args.format,
+            &matches,
+            &mut unified_diffs,
+            &mut report,
+        );
+        finish_diagnostics(&mut *args.format.output(), &args.format);
+        // The patch is written even if it's empty, so it's never stale.
+        if let Some(patch) = &args.patch {
+            if let Err(error) = std::fs::write(patch, unified_diffs) {
+                exit_with_io_error("writing patch", Some(patch), error, &args.format);
+            }
+        }
+        print_report_document(&report, &args.format);
+        std::process::exit(exit_code);
+    }
+    let destination = match args.output {
+        Some(path) => Destination::Output(path),
+        None if args.dry_run => Destination::Diff {
+            color: args.color.enabled(),
+        },
+        None => Destination::InPlace,
+    };
+    if destination.is_stdout() && args.format.report.is_some() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "Cannot print both the report and the formatted spec or diff to stdout",
+            )
+            .exit();
+    }
+    let mut report = report::Report::default();
+    let mut changed = false;
+    let mut quit = false;
+    for filename in filenames {
+        // Quitting skips the changes to all remaining specs.
+        if quit {
+            break;
+        }
+        let options = configured(&args.format, &matches, &filename);
+        if args.backup {
+            let backup = backup_path(&filename);
+            if let Err(error) = std::fs::copy(&filename, &backup) {
+                exit_with_io_error("writing backup", Some(&backup), error, &options);
+            }
+        }
+        changed |= format_file(
+            &filename,
+            args.force,
+            &destination,
+            args.interactive.then_some(&mut quit),
+            &options,
+            &mut report,
+        );
+    }
+    if destination.is_stdout() {
+        finish_diagnostics(&mut io::stderr(), &args.format);
+    } else {
+        finish_diagnostics(&mut *args.format.output(), &args.format);
+    }
+    print_report_document(&report, &args.format);
+    exit_after_formatting(changed, args.fail_on_change);
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use rewrapper::RewrapOptions;
     use test_generator::test_resources;
 
+    // Formats `input` with `format_spec()` like the command line tool would,
+    // once for each line of the `flags` file in its directory. Each line is the
+    // suffix that replaces `in.html` in the name of the file with the expected
+    // output, followed by the flags to format with, like `out.html --full-spec
+    // --wrap 80`. The flags in a `.flags` file next to `input` are added to
+    // those of every line, and a `.diff` file next to it scopes formatting to
+    // the changes in it, like those in git. Directories without a `flags` file
+    // test something other than formatting, with a test of their own below.
+    fn format_fixture(input: &str) {
+        let flags = Path::new(input).parent().unwrap().join("flags");
+        if !flags.exists() {
+            return;
+        }
+        let (_flags_file, flags_string) = read_file(&flags).unwrap();
+        let read_if_exists = |path: String| {
+            if Path::new(&path).exists() {
+                read_file(Path::new(&path)).unwrap().1
+            } else {
+                String::new()
+            }
+        };
+        let spec_flags = read_if_exists(input.replace("in.html", "flags"));
+        let diff_string = read_if_exists(input.replace("in.html", "diff"));
+        let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
+
+        for line in flags_string.lines().filter(|line| !line.trim().is_empty()) {
+            let mut words = line.split_whitespace();
+            let output = input.replace("in.html", words.next().unwrap());
+            let (_out_file, out_string) = read_file(Path::new(&output)).unwrap();
+
+            let args = ["specfmt"]
+                .into_iter()
+                .chain(words)
+                .chain(spec_flags.split_whitespace());
+            let options = Args::parse_from(args).format;
+            let diff = sanitized_diff_lines(&diff_string, options.word_diff);
+            let rewrapped = format_spec(in_string.clone(), &diff, &options).unwrap();
+            assert_eq!(rewrapped.lines.join("\n"), out_string, "{}", output);
+        }
+    }
+
     #[test_resources("testcases/*.in.html")]
-    fn simple_rewrap_tests(input: &str) {
+    fn format_tests(input: &str) {
+        format_fixture(input);
+    }
+
+    #[test_resources("testcases/*/*.in.html")]
+    fn format_directory_tests(input: &str) {
+        format_fixture(input);
+    }
+
+    #[test]
+    fn format_json_in_scope() {
+        let options = Args::parse_from(["specfmt", "--format-json", "--lines", "5:7"]).format;
+        let contents = "<pre class=\"highlight json\">\n{\"a\": [1, 2]}\n</pre>\n\n\
+                        <pre class=\"highlight json\">\n{\"b\": true}\n</pre>\n";
+        let rewrapped = format_spec(String::from(contents), &Vec::new(), &options).unwrap();
+        assert_eq!(
+            rewrapped.lines.join("\n"),
+            "<pre class=\"highlight json\">\n{\"a\": [1, 2]}\n</pre>\n\n\
+             <pre class=\"highlight json\">\n{\n  \"b\": true\n}\n</pre>\n"
+        );
+        assert_eq!(rewrapped.report.lines_to_format, 5);
+    }
+
+    #[test_resources("testcases/diff/*.in.html")]
+    fn diff_tests(input: &str) {
+        assert!(Path::new(input).exists());
+        let output = input.replace("in.html", "out.html");
+        let diff = input.replace("in.html", "diff");
+        assert!(Path::new(&output).exists());
+        assert!(Path::new(&diff).exists());
+
+        let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
+        let (_out_file, out_string) = read_file(Path::new(&output)).unwrap();
+        let (_diff_file, diff_string) = read_file(Path::new(&diff)).unwrap();
+
+        assert_eq!(
+            diff::unified_diff(&in_string, &out_string, "spec.bs"),
+            diff_string
+        );
+
+        let hunks = diff::hunks(&in_string, &out_string);
+        let all = vec![true; hunks.len()];
+        let none = vec![false; hunks.len()];
+        assert_eq!(diff::apply_hunks(&in_string, &hunks, &all), out_string);
+        assert_eq!(diff::apply_hunks(&in_string, &hunks, &none), in_string);
+    }
+
+    #[test_resources("testcases/interactive/*.in.html")]
+    fn interactive_tests(input: &str) {
+        assert!(Path::new(input).exists());
+        let output = input.replace("in.html", "out.html");
+        let answers = input.replace("in.html", "answers");
+        assert!(Path::new(&output).exists());
+        assert!(Path::new(&answers).exists());
+
+        let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
+        let (_out_file, out_string) = read_file(Path::new(&output)).unwrap();
+        let (_answers_file, answers_string) = read_file(Path::new(&answers)).unwrap();
+
+        let options = Args::parse_from(["specfmt", "--full-spec"]).format;
+        let formatted = format_spec(in_string.clone(), &Vec::new(), &options)
+            .unwrap()
+            .lines
+            .join("\n");
+        let hunks = diff::hunks(&in_string, &formatted);
+        let selection = interactive::select(
+            "spec.bs",
+            &hunks,
+            &mut answers_string.as_bytes(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(
+            diff::apply_hunks(&in_string, &hunks, &selection.selected),
+            out_string
+        );
+    }
+
+    #[test_resources("testcases/color_diff/*.diff")]
+    fn color_diff_tests(input: &str) {
+        assert!(Path::new(input).exists());
+        let output = input.replace(".diff", ".color");
+        assert!(Path::new(&output).exists());
+
+        let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
+        let (_out_file, out_string) = read_file(Path::new(&output)).unwrap();
+
+        assert_eq!(colorize_diff(&in_string), out_string);
+    }
+
+    #[test]
+    fn interactive_conflicts_with_files_from() {
+        assert!(Args::try_parse_from(["specfmt", "--interactive", "--files-from", "-"]).is_err());
+        assert!(Args::try_parse_from(["specfmt", "--interactive"]).is_ok());
+    }
+
+    #[test]
+    fn invalid_wrap_directive() {
+        let options = Args::parse_from(["specfmt", "--full-spec"]).format;
+        let contents = String::from("<!-- specfmt: wrap=ten -->\n<p>Spec</p>\n");
+        let error = format_spec(contents, &Vec::new(), &options).err().unwrap();
+        assert_eq!(error.code(), "wrap-directive");
+    }
+
+    #[test]
+    fn invalid_metadata() {
+        let options = Args::parse_from(["specfmt", "--full-spec"]).format;
+        let metadata = "<pre class=metadata>\nLine Length: wide\n</pre>\n";
+        let contents = format!("{}<p>One\ntwo.\n", metadata);
+        let error = format_spec(contents.clone(), &Vec::new(), &options)
+            .err()
+            .unwrap();
+        assert_eq!(error.code(), "metadata");
+
+        // The width in the metadata doesn't matter when it's overridden.
+        let options = Args::parse_from(["specfmt", "--full-spec", "--wrap", "80"]).format;
+        let rewrapped = format_spec(contents, &Vec::new(), &options).unwrap();
+        assert_eq!(
+            rewrapped.lines.join("\n"),
+            format!("{}<p>One two.\n", metadata)
+        );
+    }
+
+    #[test]
+    fn file_lists() {
+        let directory = tempfile::tempdir().unwrap();
+        let one = directory.path().join("one.bs");
+        let two = directory.path().join("two.bs");
+        std::fs::write(&one, "").unwrap();
+        std::fs::write(&two, "").unwrap();
+        let deleted = directory.path().join("deleted.bs");
+
+        let list = directory.path().join("list");
+        for separator in ["\n", "\r\n", "\0"] {
+            let paths = [&one, &deleted, &two].map(|path| path.display().to_string());
+            std::fs::write(&list, paths.join(separator) + separator).unwrap();
+            assert_eq!(read_file_list(&list).unwrap(), [one.clone(), two.clone()]);
+        }
+        // NUL-separated lists may name files with newlines.
+        let newline = directory.path().join("new\nline.bs");
+        std::fs::write(&newline, "").unwrap();
+        std::fs::write(&list, format!("{}\0", newline.display())).unwrap();
+        assert_eq!(read_file_list(&list).unwrap(), [newline]);
+    }
+
+    #[test]
+    fn allowed_long_lines_do_not_overflow() {
+        let contents = "<p>https://example.com/a/long/url</p>\n<p>Averylongwordthatoverflows</p>\n";
+        let overflowing = |flags: &[&str]| -> Vec<usize> {
+            let args = ["specfmt", "--full-spec", "--wrap", "20"];
+            let options = Args::parse_from(args.iter().chain(flags)).format;
+            let rewrapped = format_spec(String::from(contents), &Vec::new(), &options).unwrap();
+            rewrapped
+                .report
+                .warnings
+                .iter()
+                .map(|warning| match warning {
+                    rewrapper::Warning::Overflow { line } => *line,
+                    _ => panic!("Unexpected warning"),
+                })
+                .collect()
+        };
+        assert_eq!(overflowing(&[]), [0, 1]);
+        assert_eq!(overflowing(&["--allow-long-line", "https://"]), [1]);
+        assert_eq!(
+            overflowing(&["--allow-long-line", "https://", "--allow-long-line", "word"]),
+            []
+        );
+    }
+
+    #[test]
+    fn write_file_replaces_the_spec() {
+        let directory = tempfile::tempdir().unwrap();
+        let spec = directory.path().join("index.bs");
+        std::fs::write(&spec, "<p>Old</p>\n").unwrap();
+
+        write_file(&spec, String::from("<p>New</p>\n")).unwrap();
+        assert_eq!(std::fs::read_to_string(&spec).unwrap(), "<p>New</p>\n");
+        // The temporary file was renamed over the spec.
+        assert_eq!(std::fs::read_dir(directory.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_file_keeps_permissions_and_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+        let directory = tempfile::tempdir().unwrap();
+        let spec = directory.path().join("index.bs");
+        std::fs::write(&spec, "<p>Old</p>\n").unwrap();
+        std::fs::set_permissions(&spec, std::fs::Permissions::from_mode(0o640)).unwrap();
+        let link = directory.path().join("link.bs");
+        std::os::unix::fs::symlink(&spec, &link).unwrap();
+
+        write_file(&link, String::from("<p>New</p>\n")).unwrap();
+        assert!(link.is_symlink());
+        assert_eq!(std::fs::read_to_string(&spec).unwrap(), "<p>New</p>\n");
+        let permissions = std::fs::metadata(&spec).unwrap().permissions();
+        assert_eq!(permissions.mode() & 0o777, 0o640);
+    }
+
+    #[test_resources("testcases/sections/*.in.html")]
+    fn sections_tests(input: &str) {
         assert!(Path::new(input).exists());
         let output = input.replace("in.html", "out.html");
+        let sections = input.replace("in.html", "sections");
         assert!(Path::new(&output).exists());
+        assert!(Path::new(&sections).exists());
 
         let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
         let (_out_file, out_string) = read_file(Path::new(&output)).unwrap();
+        let (_sections_file, sections_string) = read_file(Path::new(&sections)).unwrap();
+
+        let hunks = diff::hunks(&in_string, &out_string);
+        let changed_sections = sections::changed_sections(&in_string, &hunks);
+        assert_eq!(
+            changed_sections,
+            sections_string.lines().collect::<Vec<&str>>()
+        );
+    }
+
+    #[test_resources("testcases/warnings/*.in.html")]
+    fn warnings_tests(input: &str) {
+        assert!(Path::new(input).exists());
+        let warnings = input.replace("in.html", "warnings");
+        assert!(Path::new(&warnings).exists());
+
+        let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
+        let (_warnings_file, warnings_string) = read_file(Path::new(&warnings)).unwrap();
 
         let lines: Vec<Line> = in_string
             .split("\n")
@@ -315,41 +2213,134 @@ mod test {
             .collect();
         let length = lines.len();
 
-        // Initiate unwrapping/rewrapping.
-        let wrapped_lines = rewrapper::rewrap_lines(lines, length, 100);
-        let file_as_string: String = wrapped_lines.join("\n");
-        assert_eq!(file_as_string, out_string);
+        let rewrapped = rewrapper::rewrap_lines(lines, length, &RewrapOptions::default());
+        let warnings: Vec<String> = rewrapped
+            .report
+            .warnings
+            .iter()
+            .map(|warning| match warning {
+                rewrapper::Warning::UnclosedExemptBlock { tag, line } => {
+                    format!("{}: UnclosedExemptBlock {}", line + 1, tag)
+                }
+                rewrapper::Warning::Overflow { line } => format!("{}: Overflow", line + 1),
+            })
+            .collect();
+        assert_eq!(warnings, warnings_string.lines().collect::<Vec<&str>>());
     }
 
-    #[test_resources("testcases/git_diff/*.in.html")]
-    fn git_diff_tests(input: &str) {
+    #[test_resources("testcases/diagnostics/*.in.html")]
+    fn diagnostics_tests(input: &str) {
         assert!(Path::new(input).exists());
-        let output = input.replace("in.html", "out.html");
-        let diff = input.replace("in.html", "diff");
+        let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
+
+        for error_format in ["human", "short", "json", "github", "sarif"] {
+            let output = input.replace("in.html", error_format);
+            assert!(Path::new(&output).exists());
+            let (_out_file, out_string) = read_file(Path::new(&output)).unwrap();
+
+            let options =
+                Args::parse_from(["specfmt", "--full-spec", "--error-format", error_format]).format;
+            let rewrapped = format_spec(in_string.clone(), &Vec::new(), &options).unwrap();
+            let mut report = Vec::<u8>::new();
+            print_report(
+                &mut report,
+                Path::new("spec.bs"),
+                &rewrapped.report,
+                &options,
+            );
+            diagnostics::finish(&mut report, options.error_format()).unwrap();
+            assert_eq!(String::from_utf8(report).unwrap(), out_string);
+        }
+    }
+
+    // Runs `serve` on the messages on each line of `input`, and checks that it
+    // responds with the messages on each line of its `responses` file.
+    fn jsonrpc_session_test(input: &str, serve: impl FnOnce(&mut &[u8], &mut Vec<u8>)) {
+        assert!(Path::new(input).exists());
+        let output = input.replace("requests", "responses");
         assert!(Path::new(&output).exists());
-        assert!(Path::new(&diff).exists());
 
         let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
         let (_out_file, out_string) = read_file(Path::new(&output)).unwrap();
-        let (_diff_file, diff_string) = read_file(Path::new(&diff)).unwrap();
 
-        let mut lines: Vec<Line> = in_string
-            .split("\n")
-            .map(|line| Line {
-                // Exempt all lines from formatting. `apply_diff()` below will
-                // reverse this for lines included in the diff.
-                should_format: false,
-                contents: line,
-            })
-            .collect();
-        let length = lines.len();
+        let mut requests = Vec::<u8>::new();
+        for request in in_string.lines() {
+            jsonrpc::write_message(&mut requests, &jsonrpc::parse(request).unwrap()).unwrap();
+        }
+        let mut responses = Vec::<u8>::new();
+        serve(&mut requests.as_slice(), &mut responses);
+
+        let mut responses = responses.as_slice();
+        let mut response_lines = Vec::new();
+        while let Some(response) = jsonrpc::read_message(&mut responses).unwrap() {
+            response_lines.push(response);
+        }
+        assert_eq!(response_lines, out_string.lines().collect::<Vec<&str>>());
+    }
+
+    #[test_resources("testcases/lsp/*.requests")]
+    fn lsp_tests(input: &str) {
+        // A session may be served with the extra flags in its `flags` file.
+        let flags = input.replace("requests", "flags");
+        let flags = if Path::new(&flags).exists() {
+            read_file(Path::new(&flags)).unwrap().1
+        } else {
+            String::new()
+        };
+        let args = ["specfmt", "--lsp", "--full-spec"]
+            .into_iter()
+            .chain(flags.split_whitespace());
+        let matches = Args::command().get_matches_from(args);
+        let options = Args::from_arg_matches(&matches).unwrap().format;
+        jsonrpc_session_test(input, |requests, responses| {
+            let exit_code = lsp::Server::new(&options, &matches)
+                .serve(requests, responses)
+                .unwrap();
+            assert_eq!(exit_code, 0);
+        });
+    }
 
-        let diff = sanitized_diff_lines(&diff_string);
-        apply_diff(&mut lines, &diff);
+    #[test_resources("testcases/daemon/*.requests")]
+    fn daemon_tests(input: &str) {
+        jsonrpc_session_test(input, |requests, responses| {
+            assert!(daemon::serve_connection(requests, responses).unwrap());
+        });
+    }
+
+    #[test_resources("testcases/report/*.in.html")]
+    fn report_tests(input: &str) {
+        assert!(Path::new(input).exists());
+        let output = input.replace("in.html", "json");
+        assert!(Path::new(&output).exists());
+
+        let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
+        let (_out_file, out_string) = read_file(Path::new(&output)).unwrap();
+
+        let options = Args::parse_from(["specfmt", "--full-spec", "--report", "json"]).format;
+        let rewrapped = format_spec(in_string.clone(), &Vec::new(), &options).unwrap();
+        let mut report = report::Report::default();
+        report.add(
+            "spec.bs",
+            &in_string,
+            &rewrapped.lines.join("\n"),
+            &rewrapped.report,
+        );
+        assert_eq!(
+            report.render(report::ReportFormat::Json),
+            out_string.trim_end()
+        );
+    }
+
+    #[test_resources("testcases/config/*.toml")]
+    fn config_tests(input: &str) {
+        assert!(Path::new(input).exists());
+        let output = input.replace("toml", "flags");
+        assert!(Path::new(&output).exists());
+
+        let (_in_file, in_string) = read_file(Path::new(input)).unwrap();
+        let (_out_file, out_string) = read_file(Path::new(&output)).unwrap();
 
-        // Initiate unwrapping/rewrapping.
-        let wrapped_lines = rewrapper::rewrap_lines(lines, length, 100);
-        let file_as_string: String = wrapped_lines.join("\n");
-        assert_eq!(file_as_string, out_string);
+        let flags = config::parse(&in_string).unwrap();
+        assert_eq!(flags, out_string.lines().collect::<Vec<&str>>());
     }
 }
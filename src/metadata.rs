@@ -0,0 +1,96 @@
+use super::SpecfmtError;
+use super::Wrap;
+
+// Reads the formatting conventions of a Bikeshed spec from its metadata block,
+// so that specs need no configuration beyond what they already declare:
+//
+//   <pre class=metadata>
+//   Title: Example Standard
+//   Line Length: 80
+//   Markup Shorthands: css no, markdown yes
+//   </pre>
+//
+// The width comes from a `Specfmt` key (or `!Specfmt`, as Bikeshed spells
+// custom keys) of the form `wrap=COLUMNS`, or else from `Line Length`. Whether
+// the spec uses markdown comes from `Markup Shorthands`, if it mentions
+// markdown at all.
+
+// The formatting options that a spec's metadata implies, if any. A width
+// that specfmt doesn't understand is an error, rather than silently using some
+// other width, but only once the width is needed: it doesn't matter when
+// `--wrap` overrides it.
+pub struct Metadata {
+    pub wrap: Result<Option<Wrap>, SpecfmtError>,
+    pub markdown: Option<bool>,
+}
+
+// Reads the metadata block of `contents`, if it has one.
+pub fn parse(contents: &str) -> Metadata {
+    let mut specfmt = None;
+    let mut line_length = None;
+    let mut markdown = None;
+    let lines = contents
+        .split('\n')
+        .enumerate()
+        .skip_while(|(_, line)| !is_metadata_start(line))
+        .skip(1);
+    for (i, line) in lines {
+        let line = line.trim();
+        if line.eq_ignore_ascii_case("</pre>") {
+            break;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let error = |message: String| {
+            SpecfmtError::Metadata(format!("Invalid metadata on line {}: {}", i + 1, message))
+        };
+        if key.eq_ignore_ascii_case("Specfmt") || key.eq_ignore_ascii_case("!Specfmt") {
+            let wrap = value
+                .strip_prefix("wrap=")
+                .ok_or_else(|| format!("'{}' is not of the form wrap=COLUMNS", value))
+                .and_then(super::parse_wrap)
+                .map_err(error);
+            specfmt = Some(wrap);
+        } else if key.eq_ignore_ascii_case("Line Length") {
+            let columns = super::parse_columns(value).map_err(error);
+            line_length = Some(columns.map(Wrap::Columns));
+        } else if key.eq_ignore_ascii_case("Markup Shorthands") {
+            for shorthand in value.split(',') {
+                match shorthand.split_whitespace().collect::<Vec<_>>()[..] {
+                    [name, "yes"] if name.eq_ignore_ascii_case("markdown") => markdown = Some(true),
+                    [name, "no"] if name.eq_ignore_ascii_case("markdown") => markdown = Some(false),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Metadata {
+        wrap: specfmt.or(line_length).transpose(),
+        markdown,
+    }
+}
+
+// Whether `line` is the `<pre class=metadata>` tag that starts a metadata
+// block.
+fn is_metadata_start(line: &str) -> bool {
+    let Some(attributes) = line
+        .trim()
+        .strip_prefix("<pre")
+        .and_then(|line| line.strip_suffix('>'))
+    else {
+        return false;
+    };
+    if !attributes.starts_with(char::is_whitespace) {
+        return false;
+    }
+    let Some((_, class)) = attributes.split_once("class=") else {
+        return false;
+    };
+    let class = match class.strip_prefix(['"', '\'']) {
+        Some(quoted) => quoted.split(['"', '\'']).next().unwrap_or(""),
+        None => class.split_whitespace().next().unwrap_or(""),
+    };
+    class.split_whitespace().any(|class| class == "metadata")
+}
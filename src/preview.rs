@@ -0,0 +1,233 @@
+use specfmt_core::diff;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+
+// A tiny local web server that renders the changes formatting would make to a
+// spec as a side-by-side diff, with a checkbox per hunk. Submitting the page
+// returns the selected hunks to the caller, which applies them. Reviewing a
+// large reformat in a browser is much easier than in a terminal.
+//
+// We only ever talk to a browser on the same machine, so this implements just
+// enough of HTTP/1.1 for that: one request per connection, and no keep-alive.
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Appends rows pairing up the lines of a run of `removed` and `added` lines.
+fn flush_rows(removed: &mut Vec<&str>, added: &mut Vec<&str>, rows: &mut String) {
+    let cell = |lines: &Vec<&str>, i: usize, class: &str| match lines.get(i) {
+        Some(line) => format!("<td class={}>{}</td>", class, escape_html(line)),
+        None => String::from("<td></td>"),
+    };
+    for i in 0..usize::max(removed.len(), added.len()) {
+        rows.push_str(&format!(
+            "<tr>{}{}</tr>\n",
+            cell(removed, i, "removed"),
+            cell(added, i, "added")
+        ));
+    }
+    removed.clear();
+    added.clear();
+}
+
+// Renders `hunk` as the rows of a side-by-side table. Removed lines go on the
+// left, added lines on the right, and context lines on both sides.
+fn render_hunk(hunk: &diff::Hunk) -> String {
+    let mut rows = String::new();
+    let mut removed = Vec::<&str>::new();
+    let mut added = Vec::<&str>::new();
+    for line in &hunk.lines {
+        match line.chars().next() {
+            Some('-') => removed.push(&line[1..]),
+            Some('+') => added.push(&line[1..]),
+            Some(' ') => {
+                flush_rows(&mut removed, &mut added, &mut rows);
+                let line = escape_html(&line[1..]);
+                rows.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>\n", line, line));
+            }
+            _ => {}
+        }
+    }
+    flush_rows(&mut removed, &mut added, &mut rows);
+    rows
+}
+
+fn render_page(path: &str, hunks: &[diff::Hunk]) -> String {
+    let mut page = format!(
+        r#"<!DOCTYPE html>
+<meta charset=utf-8>
+<title>specfmt preview: {path}</title>
+<style>
+  body {{ font-family: sans-serif; margin: 2em; }}
+  table {{ border-collapse: collapse; width: 100%; table-layout: fixed; margin-bottom: 2em; }}
+  td {{ font-family: monospace; white-space: pre-wrap; vertical-align: top; padding: 0 .5em; }}
+  td.removed {{ background: #fdd; }}
+  td.added {{ background: #dfd; }}
+  th {{ text-align: left; }}
+</style>
+<h1>Formatting changes to <code>{path}</code></h1>
+<form method=post action=/apply>
+"#,
+        path = escape_html(path)
+    );
+    for (i, hunk) in hunks.iter().enumerate() {
+        page.push_str(&format!(
+            "<table>\n<tr><th colspan=2><label><input type=checkbox name=hunk value={} checked> \
+             Lines {}&ndash;{}</label></th></tr>\n{}</table>\n",
+            i,
+            hunk.old_start,
+            hunk.old_start + hunk.old_len.saturating_sub(1),
+            render_hunk(hunk)
+        ));
+    }
+    page.push_str("<button>Apply the selected changes</button>\n</form>\n");
+    page
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// Reads a request from `stream`, returning its request line and body.
+fn read_request(stream: &TcpStream) -> io::Result<(String, String)> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok((
+        request_line.trim().to_string(),
+        String::from_utf8_lossy(&body).to_string(),
+    ))
+}
+
+// Serves a preview of `hunks` to `path` on `port` (0 picks any free port) until
+// the user submits their selection, and returns which hunks they selected.
+pub fn serve(path: &str, hunks: &[diff::Hunk], port: u16) -> io::Result<Vec<bool>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!(
+        "Previewing {} formatting changes to '{}' at http://{}/",
+        hunks.len(),
+        path,
+        listener.local_addr()?
+    );
+
+    let page = render_page(path, hunks);
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let (request_line, body) = read_request(&stream)?;
+        if request_line.starts_with("GET / ") {
+            respond(&mut stream, "200 OK", &page)?;
+        } else if request_line.starts_with("POST /apply ") {
+            // The form is submitted as `hunk=0&hunk=2`, listing checked boxes.
+            let mut selected = vec![false; hunks.len()];
+            for pair in body.split('&') {
+                if let Some(Ok(i)) = pair.strip_prefix("hunk=").map(str::parse::<usize>) {
+                    if i < selected.len() {
+                        selected[i] = true;
+                    }
+                }
+            }
+            let count = selected.iter().filter(|&&selected| selected).count();
+            respond(
+                &mut stream,
+                "200 OK",
+                &format!(
+                    "<!DOCTYPE html>\n<meta charset=utf-8>\n<title>specfmt preview</title>\n\
+                     <p>Applied {} of {} changes to <code>{}</code>. You can close this page.\n",
+                    count,
+                    hunks.len(),
+                    escape_html(path)
+                ),
+            )?;
+            return Ok(selected);
+        } else {
+            respond(&mut stream, "404 Not Found", "Not found")?;
+        }
+    }
+
+    unreachable!("`TcpListener::incoming()` never ends")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    // Sends `request` to the preview on `port`, retrying until it's listening,
+    // and returns the response.
+    fn request(port: u16, request: &str) -> String {
+        let mut stream = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(_) => thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        };
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serve_returns_the_selected_hunks() {
+        let unchanged = "\n<p>Two</p>\n<p>Three</p>\n<p>Four</p>\n<p>Five</p>\n<p>Six</p>\n\n";
+        let hunks = diff::hunks(
+            &format!("<p>One\none</p>\n{}<p>Seven\n<b>seven</b></p>\n", unchanged),
+            &format!("<p>One one</p>\n{}<p>Seven <b>seven</b></p>\n", unchanged),
+        );
+        assert_eq!(hunks.len(), 2);
+        // A port that was free a moment ago.
+        let port = TcpListener::bind(("127.0.0.1", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server = thread::spawn(move || serve("spec.bs", &hunks, port));
+
+        let page = request(port, "GET / HTTP/1.1\r\n\r\n");
+        assert!(page.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(page.contains("<td class=removed>&lt;b&gt;seven&lt;/b&gt;&lt;/p&gt;</td>"));
+        assert!(page.contains("name=hunk value=1 checked"));
+        let body = "hunk=1";
+        let applied = request(
+            port,
+            &format!(
+                "POST /apply HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        );
+        assert!(applied.contains("Applied 1 of 2 changes to <code>spec.bs</code>"));
+        assert_eq!(server.join().unwrap().unwrap(), [false, true]);
+    }
+}
@@ -0,0 +1,140 @@
+use super::jsonrpc::Value;
+use specfmt_core::diff;
+use specfmt_core::rewrapper;
+
+// A machine-readable report of the changes that formatting makes (or would
+// make) to specs, for bots that turn them into review comments. For example:
+//
+//   {"files": [{"file": "spec.bs", "changes": [
+//     {"line": 4, "endLine": 5, "reason": "unwrap", "newText": "..."},
+//     {"line": 9, "endLine": 12, "reason": "exempt"}]}]}
+//
+// Lines are 1-based and inclusive, and refer to the spec before formatting. The
+// reason for a change is one of:
+//   - "unwrap": lines were joined into fewer lines.
+//   - "rewrap": lines were broken differently, into as many lines or more.
+//   - "exempt": the lines were subject to formatting, but left alone because
+//     they are exempt from it (like a `<pre>` block). These have no `newText`.
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+}
+
+struct Change {
+    start: usize,
+    end: usize,
+    reason: &'static str,
+    new_text: Option<String>,
+}
+
+// Splits the differences between `original` and `formatted` into runs of
+// changed lines, which hunks group together when they're close.
+fn changes(original: &str, formatted: &str) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for hunk in diff::hunks(original, formatted) {
+        let mut line = hunk.old_start;
+        let mut removed = Vec::<&str>::new();
+        let mut added = Vec::<&str>::new();
+        let mut start = line;
+        // The trailing context line flushes the last run.
+        for hunk_line in hunk.lines.iter().map(String::as_str).chain([" "]) {
+            match hunk_line.chars().next() {
+                Some('\\') => continue,
+                Some('-') => {
+                    if removed.is_empty() && added.is_empty() {
+                        start = line;
+                    }
+                    removed.push(&hunk_line[1..]);
+                    line += 1;
+                }
+                Some('+') => {
+                    if removed.is_empty() && added.is_empty() {
+                        start = line;
+                    }
+                    added.push(&hunk_line[1..]);
+                }
+                _ => {
+                    if !removed.is_empty() || !added.is_empty() {
+                        // Insertions are attributed to the line they follow,
+                        // like `Hunk::changed_old_lines()` does.
+                        let (start, end) = match removed.len() {
+                            0 => {
+                                let insertion = usize::max(start.saturating_sub(1), 1);
+                                (insertion, insertion)
+                            }
+                            removed => (start, start + removed - 1),
+                        };
+                        changes.push(Change {
+                            start,
+                            end,
+                            reason: if added.len() < removed.len() {
+                                "unwrap"
+                            } else {
+                                "rewrap"
+                            },
+                            new_text: Some(added.join("\n")),
+                        });
+                        removed.clear();
+                        added.clear();
+                    }
+                    line += 1;
+                }
+            }
+        }
+    }
+    changes
+}
+
+#[derive(Default)]
+pub struct Report {
+    files: Vec<Value>,
+}
+
+impl Report {
+    // Adds the changes from `original` to `formatted` of the spec `file`,
+    // along with the exempt lines from its formatting `report`.
+    pub fn add(
+        &mut self,
+        file: &str,
+        original: &str,
+        formatted: &str,
+        report: &rewrapper::FormatReport,
+    ) {
+        let mut changes = changes(original, formatted);
+        changes.extend(report.exempt_lines.iter().map(|&(start, end)| Change {
+            start: start + 1,
+            end: end + 1,
+            reason: "exempt",
+            new_text: None,
+        }));
+        changes.sort_by_key(|change| change.start);
+
+        let changes = changes
+            .into_iter()
+            .map(|change| {
+                let mut members = vec![
+                    ("line", Value::from(change.start)),
+                    ("endLine", Value::from(change.end)),
+                    ("reason", Value::from(change.reason)),
+                ];
+                if let Some(new_text) = change.new_text {
+                    members.push(("newText", Value::String(new_text)));
+                }
+                Value::object(members)
+            })
+            .collect();
+        self.files.push(Value::object(vec![
+            ("file", Value::from(file)),
+            ("changes", Value::Array(changes)),
+        ]));
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Json => {
+                Value::object(vec![("files", Value::Array(self.files.clone()))]).to_string()
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+
+// Tests of the command line tool as scripts and editors run it, for behavior
+// that depends on its exit code, stdin and stdout, or the files it writes.
+
+// A spec that needs formatting, and the spec formatted.
+const UNFORMATTED: &str = "<p>One two\nthree four five six.</p>\n";
+const FORMATTED: &str = "<p>One two three four five six.</p>\n";
+
+// Runs specfmt with `args` in `directory`, writing `stdin` to it.
+fn specfmt(directory: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_specfmt"))
+        .args(args)
+        .current_dir(directory)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn stdin_filepath_finds_the_configuration_of_the_spec() {
+    let directory = tempfile::tempdir().unwrap();
+    fs::create_dir(directory.path().join("spec")).unwrap();
+    fs::write(directory.path().join("spec/.specfmt.toml"), "wrap = 20\n").unwrap();
+
+    let args = ["--full-spec", "--stdin-filepath", "spec/index.bs"];
+    let output = specfmt(directory.path(), &args, UNFORMATTED);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "<p>One two three\nfour five six.</p>\n");
+    // Without it, the spec isn't known to be in that directory.
+    let output = specfmt(directory.path(), &["-"], UNFORMATTED);
+    assert_eq!(stdout(&output), FORMATTED);
+}
+
+#[test]
+fn check_reports_without_writing() {
+    let directory = tempfile::tempdir().unwrap();
+    let spec = directory.path().join("index.bs");
+    fs::write(&spec, UNFORMATTED).unwrap();
+
+    let args = ["--check", "--full-spec", "index.bs"];
+    let output = specfmt(directory.path(), &args, "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("index.bs:1-2: This section of the spec needs formatting"));
+    assert_eq!(fs::read_to_string(&spec).unwrap(), UNFORMATTED);
+
+    fs::write(&spec, FORMATTED).unwrap();
+    let output = specfmt(directory.path(), &args, "");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn dry_run_prints_the_diff() {
+    let directory = tempfile::tempdir().unwrap();
+    let spec = directory.path().join("index.bs");
+    fs::write(&spec, UNFORMATTED).unwrap();
+
+    let output = specfmt(
+        directory.path(),
+        &["--dry-run", "--full-spec", "index.bs"],
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "--- a/index.bs\n+++ b/index.bs\n@@ -1,2 +1 @@\n\
+         -<p>One two\n-three four five six.</p>\n+<p>One two three four five six.</p>\n"
+    );
+    assert_eq!(fs::read_to_string(&spec).unwrap(), UNFORMATTED);
+
+    let args = ["--dry-run", "--color", "always", "--full-spec", "index.bs"];
+    let output = specfmt(directory.path(), &args, "");
+    assert!(stdout(&output).contains("\x1b[31m-<p>One two\x1b[0m\n"));
+}
+
+#[test]
+fn format_stdin_to_stdout() {
+    let directory = tempfile::tempdir().unwrap();
+    let output = specfmt(directory.path(), &["-"], UNFORMATTED);
+    assert_eq!(output.status.code(), Some(0));
+    // Only the spec goes to stdout.
+    assert_eq!(stdout(&output), FORMATTED);
+
+    let output = specfmt(directory.path(), &["-", "--lines", "1:1"], UNFORMATTED);
+    assert_eq!(stdout(&output), UNFORMATTED);
+    let output = specfmt(directory.path(), &["-", "--check"], UNFORMATTED);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn exit_codes_with_fail_on_change() {
+    let directory = tempfile::tempdir().unwrap();
+    let spec = directory.path().join("index.bs");
+    fs::write(&spec, UNFORMATTED).unwrap();
+
+    let args = ["--full-spec", "--fail-on-change", "index.bs"];
+    let output = specfmt(directory.path(), &args, "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(fs::read_to_string(&spec).unwrap(), FORMATTED);
+    let output = specfmt(directory.path(), &args, "");
+    assert_eq!(output.status.code(), Some(0));
+
+    fs::write(&spec, UNFORMATTED).unwrap();
+    let output = specfmt(directory.path(), &["--full-spec", "index.bs"], "");
+    assert_eq!(output.status.code(), Some(0));
+    let output = specfmt(directory.path(), &["--full-spec", "missing.bs"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn patch_applies_to_the_spec() {
+    let directory = tempfile::tempdir().unwrap();
+    fs::create_dir(directory.path().join("spec")).unwrap();
+    let spec = directory.path().join("spec/index.bs");
+    fs::write(&spec, UNFORMATTED).unwrap();
+
+    let args = ["--patch", "format.patch", "--full-spec", "./spec/index.bs"];
+    let output = specfmt(directory.path(), &args, "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(fs::read_to_string(&spec).unwrap(), UNFORMATTED);
+    assert_eq!(
+        fs::read_to_string(directory.path().join("format.patch")).unwrap(),
+        "--- a/spec/index.bs\n+++ b/spec/index.bs\n@@ -1,2 +1 @@\n\
+         -<p>One two\n-three four five six.</p>\n+<p>One two three four five six.</p>\n"
+    );
+
+    // The patch is emptied rather than left stale once there's nothing to do.
+    fs::write(&spec, FORMATTED).unwrap();
+    let output = specfmt(directory.path(), &args, "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(directory.path().join("format.patch")).unwrap(),
+        ""
+    );
+}
+
+#[test]
+fn backup_and_revert() {
+    let directory = tempfile::tempdir().unwrap();
+    let spec = directory.path().join("index.bs");
+    let backup = directory.path().join("index.bs.specfmt.orig");
+    fs::write(&spec, UNFORMATTED).unwrap();
+
+    let output = specfmt(
+        directory.path(),
+        &["--backup", "--full-spec", "index.bs"],
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&spec).unwrap(), FORMATTED);
+    assert_eq!(fs::read_to_string(&backup).unwrap(), UNFORMATTED);
+
+    let output = specfmt(directory.path(), &["--revert", "index.bs"], "");
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&spec).unwrap(), UNFORMATTED);
+    assert!(!backup.exists());
+
+    let output = specfmt(directory.path(), &["--revert", "index.bs"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("No backup to revert to"));
+    assert_eq!(fs::read_to_string(&spec).unwrap(), UNFORMATTED);
+}
+
+#[test]
+fn output_to_another_file() {
+    let directory = tempfile::tempdir().unwrap();
+    let spec = directory.path().join("index.bs");
+    fs::write(&spec, UNFORMATTED).unwrap();
+
+    let output = specfmt(
+        directory.path(),
+        &["-o", "out.bs", "--full-spec", "index.bs"],
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&spec).unwrap(), UNFORMATTED);
+    let formatted = directory.path().join("out.bs");
+    assert_eq!(fs::read_to_string(formatted).unwrap(), FORMATTED);
+
+    let output = specfmt(
+        directory.path(),
+        &["-o", "-", "--full-spec", "index.bs"],
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), FORMATTED);
+    assert_eq!(fs::read_to_string(&spec).unwrap(), UNFORMATTED);
+}
+
+#[test]
+fn errors_are_reported_rather_than_panicking() {
+    let directory = tempfile::tempdir().unwrap();
+    fs::write(directory.path().join("index.bs"), UNFORMATTED).unwrap();
+
+    let args = [
+        "--full-spec",
+        "--error-format=short",
+        "-o",
+        "missing/out.bs",
+        "index.bs",
+    ];
+    let output = specfmt(directory.path(), &args, "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("missing/out.bs: error[io]: Error writing file: "));
+
+    fs::write(directory.path().join(".specfmt.toml"), "wrap = 5\n").unwrap();
+    let args = ["--full-spec", "--error-format=short", "index.bs"];
+    let output = specfmt(directory.path(), &args, "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("index.bs: error[config]: Invalid configuration in '"));
+}
+
+#[cfg(feature = "git")]
+#[test]
+fn format_the_entire_spec_outside_git() {
+    let directory = tempfile::tempdir().unwrap();
+    let spec = directory.path().join("index.bs");
+    fs::write(&spec, UNFORMATTED).unwrap();
+
+    let output = specfmt(directory.path(), &["--check", "index.bs"], "");
+    assert_eq!(output.status.code(), Some(1));
+    let output = specfmt(directory.path(), &["index.bs"], "");
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("The spec is not in a git repository"));
+    assert_eq!(fs::read_to_string(&spec).unwrap(), FORMATTED);
+}